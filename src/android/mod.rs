@@ -0,0 +1,250 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+
+use serde::Serializer;
+
+use message::Priority;
+
+/// Options for messages delivered to Android devices, sent under
+/// `android`, as FCM HTTP v1 expects. The legacy endpoints read the flat
+/// fields of `Message` instead.
+/// # Examples:
+/// ```rust
+/// use gcm::{AndroidConfig, AndroidNotification, Message, Priority};
+///
+/// let android = AndroidConfig::new()
+///     .priority(Priority::High)
+///     .ttl(3600)
+///     .notification(AndroidNotification::new().title("India vs. Australia").channel_id("scores"));
+///
+/// let message = Message::new("<registration id>").android(android);
+/// ```
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AndroidConfig {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  collapse_key: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none", serialize_with = "priority_uppercase")]
+  priority: Option<Priority>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  ttl: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  restricted_package_name: Option<String>,
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  data: HashMap<String, String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  notification: Option<AndroidNotification>,
+}
+
+// FCM v1 spells the priorities in upper case.
+fn priority_uppercase<S>(priority: &Option<Priority>, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+  match *priority {
+    Some(Priority::High) => serializer.serialize_str("HIGH"),
+    _ => serializer.serialize_str("NORMAL")
+  }
+}
+
+impl AndroidConfig {
+  /// Get a new, empty `AndroidConfig`.
+  pub fn new() -> AndroidConfig {
+    AndroidConfig::default()
+  }
+
+  /// Set this to identify groups of messages that can be collapsed.
+  pub fn collapse_key(mut self, collapse_key: &str) -> AndroidConfig {
+    self.collapse_key = Some(collapse_key.to_string());
+    self
+  }
+
+  /// Set the priority of the message.
+  pub fn priority(mut self, priority: Priority) -> AndroidConfig {
+    self.priority = Some(priority);
+    self
+  }
+
+  /// How long (in seconds) to keep the message if the device is offline.
+  pub fn ttl(mut self, seconds: u32) -> AndroidConfig {
+    self.ttl = Some(format!("{}s", seconds));
+    self
+  }
+
+  /// Package name of the application where the registration tokens must match.
+  pub fn restricted_package_name(mut self, restricted_package_name: &str) -> AndroidConfig {
+    self.restricted_package_name = Some(restricted_package_name.to_string());
+    self
+  }
+
+  /// Add a custom key-value pair, replacing the message's `data` on Android.
+  pub fn data(mut self, key: &str, value: &str) -> AndroidConfig {
+    self.data.insert(key.to_string(), value.to_string());
+    self
+  }
+
+  /// Set the notification to show on Android.
+  pub fn notification(mut self, notification: AndroidNotification) -> AndroidConfig {
+    self.notification = Some(notification);
+    self
+  }
+
+  /// The collapse key, if set.
+  pub fn get_collapse_key(&self) -> Option<&str> {
+    self.collapse_key.as_ref().map(|collapse_key| &collapse_key[..])
+  }
+
+  /// The priority, if set.
+  pub fn get_priority(&self) -> Option<Priority> {
+    self.priority
+  }
+
+  /// The time to live, as FCM expects it, e.g. `"3600s"`.
+  pub fn get_ttl(&self) -> Option<&str> {
+    self.ttl.as_ref().map(|ttl| &ttl[..])
+  }
+
+  /// The notification, if set.
+  pub fn get_notification(&self) -> Option<&AndroidNotification> {
+    self.notification.as_ref()
+  }
+}
+
+/// How much of a notification shows on the lock screen.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Visibility {
+  /// Shown in full only once the device is unlocked. Android's default.
+  #[serde(rename = "PRIVATE")]
+  Private,
+  /// Shown in full on the lock screen.
+  #[serde(rename = "PUBLIC")]
+  Public,
+  /// Not shown on the lock screen at all.
+  #[serde(rename = "SECRET")]
+  Secret
+}
+
+/// A notification shown on Android, set with `AndroidConfig::notification`.
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AndroidNotification {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  title: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  body: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  icon: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  color: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  sound: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  tag: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  click_action: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  channel_id: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  image: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  ticker: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  sticky: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  local_only: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  notification_count: Option<u32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  visibility: Option<Visibility>,
+}
+
+impl AndroidNotification {
+  /// Get a new, empty `AndroidNotification`.
+  pub fn new() -> AndroidNotification {
+    AndroidNotification::default()
+  }
+
+  /// Set the title of the notification.
+  pub fn title(mut self, title: &str) -> AndroidNotification {
+    self.title = Some(title.to_string());
+    self
+  }
+
+  /// Set the body of the notification.
+  pub fn body(mut self, body: &str) -> AndroidNotification {
+    self.body = Some(body.to_string());
+    self
+  }
+
+  /// Set the notification icon.
+  pub fn icon(mut self, icon: &str) -> AndroidNotification {
+    self.icon = Some(icon.to_string());
+    self
+  }
+
+  /// The color of the icon, in #rrggbb format.
+  pub fn color(mut self, color: &str) -> AndroidNotification {
+    self.color = Some(color.to_string());
+    self
+  }
+
+  /// Set the sound to be played.
+  pub fn sound(mut self, sound: &str) -> AndroidNotification {
+    self.sound = Some(sound.to_string());
+    self
+  }
+
+  /// Notifications with the same tag replace each other.
+  pub fn tag(mut self, tag: &str) -> AndroidNotification {
+    self.tag = Some(tag.to_string());
+    self
+  }
+
+  /// The activity to start when the user clicks on the notification.
+  pub fn click_action(mut self, click_action: &str) -> AndroidNotification {
+    self.click_action = Some(click_action.to_string());
+    self
+  }
+
+  /// Set the notification channel, needed on Android 8.0 and above.
+  pub fn channel_id(mut self, channel_id: &str) -> AndroidNotification {
+    self.channel_id = Some(channel_id.to_string());
+    self
+  }
+
+  /// Set the URL of an image to show in the notification.
+  pub fn image(mut self, image: &str) -> AndroidNotification {
+    self.image = Some(image.to_string());
+    self
+  }
+
+  /// Set the text read out by accessibility services.
+  pub fn ticker(mut self, ticker: &str) -> AndroidNotification {
+    self.ticker = Some(ticker.to_string());
+    self
+  }
+
+  /// When `true`, the notification stays when the user clicks on it.
+  pub fn sticky(mut self, sticky: bool) -> AndroidNotification {
+    self.sticky = Some(sticky);
+    self
+  }
+
+  /// When `true`, the notification isn't bridged to other devices, such
+  /// as a watch.
+  pub fn local_only(mut self, local_only: bool) -> AndroidNotification {
+    self.local_only = Some(local_only);
+    self
+  }
+
+  /// Set the number of items the notification stands for, shown as the
+  /// badge on the app icon by launchers that support it.
+  pub fn notification_count(mut self, notification_count: u32) -> AndroidNotification {
+    self.notification_count = Some(notification_count);
+    self
+  }
+
+  /// Set how much of the notification shows on the lock screen.
+  pub fn visibility(mut self, visibility: Visibility) -> AndroidNotification {
+    self.visibility = Some(visibility);
+    self
+  }
+}
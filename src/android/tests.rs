@@ -0,0 +1,101 @@
+use serde_json;
+use {AndroidConfig, AndroidNotification, Priority, Visibility};
+
+#[test]
+fn should_serialize_empty_android_config() {
+  let j = serde_json::to_value(&AndroidConfig::new()).unwrap();
+
+  assert_eq!(j, json!({}));
+}
+
+#[test]
+fn should_serialize_android_config() {
+  let android = AndroidConfig::new()
+    .collapse_key("score")
+    .priority(Priority::High)
+    .ttl(3600)
+    .restricted_package_name("com.example.scores")
+    .data("match_id", "42");
+
+  let j = serde_json::to_value(&android).unwrap();
+
+  assert_eq!(j, json!({
+    "collapse_key": "score",
+    "priority": "HIGH",
+    "ttl": "3600s",
+    "restricted_package_name": "com.example.scores",
+    "data": { "match_id": "42" }
+  }));
+}
+
+#[test]
+fn should_serialize_android_notification() {
+  let notification = AndroidNotification::new()
+    .title("India vs. Australia")
+    .body("3 runs to win in 1 ball")
+    .icon("ball")
+    .color("#00ff00")
+    .sound("default")
+    .tag("score")
+    .click_action("OPEN_MATCH")
+    .channel_id("scores")
+    .image("https://example.com/ball.png");
+  let android = AndroidConfig::new().notification(notification);
+
+  let j = serde_json::to_value(&android).unwrap();
+
+  assert_eq!(j, json!({
+    "notification": {
+      "title": "India vs. Australia",
+      "body": "3 runs to win in 1 ball",
+      "icon": "ball",
+      "color": "#00ff00",
+      "sound": "default",
+      "tag": "score",
+      "click_action": "OPEN_MATCH",
+      "channel_id": "scores",
+      "image": "https://example.com/ball.png"
+    }
+  }));
+}
+
+#[test]
+fn should_deserialize_android_config() {
+  let android: AndroidConfig = serde_json::from_str(r#"{"priority": "HIGH", "ttl": "60s"}"#).unwrap();
+
+  assert_eq!(android.get_priority(), Some(Priority::High));
+  assert_eq!(android.get_ttl(), Some("60s"));
+  assert_eq!(android.get_collapse_key(), None);
+  assert_eq!(android.get_notification(), None);
+}
+
+#[test]
+fn should_serialize_ticker_sticky_local_only_and_count() {
+  let notification = AndroidNotification::new()
+    .ticker("India won")
+    .sticky(true)
+    .local_only(false)
+    .notification_count(3);
+
+  let j = serde_json::to_value(&notification).unwrap();
+
+  assert_eq!(j, json!({
+    "ticker": "India won",
+    "sticky": true,
+    "local_only": false,
+    "notification_count": 3
+  }));
+}
+
+#[test]
+fn should_serialize_notification_visibility() {
+  let j = serde_json::to_value(&AndroidNotification::new()).unwrap();
+
+  assert!(j.get("visibility").is_none());
+
+  for &(visibility, expected) in &[(Visibility::Private, "PRIVATE"), (Visibility::Public, "PUBLIC"), (Visibility::Secret, "SECRET")] {
+    let j = serde_json::to_value(&AndroidNotification::new().visibility(visibility)).unwrap();
+
+    assert_eq!(j, json!({ "visibility": expected }));
+  }
+}
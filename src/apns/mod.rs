@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+/// Options for messages delivered to iOS devices through APNs, sent under
+/// `apns`. Only FCM understands it; GCM ignores it.
+/// # Examples:
+/// ```rust
+/// # #[macro_use] extern crate serde_json;
+/// # extern crate gcm;
+/// # fn main() {
+/// use gcm::{ApnsConfig, Message};
+///
+/// let apns = ApnsConfig::new()
+///     .priority(10)
+///     .collapse_id("score")
+///     .aps(json!({ "alert": "India won!", "badge": 1 }));
+///
+/// let message = Message::new("<registration id>").apns(apns);
+/// # }
+/// ```
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApnsConfig {
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  headers: HashMap<String, String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  payload: Option<Value>,
+}
+
+impl ApnsConfig {
+  /// Get a new, empty `ApnsConfig`.
+  pub fn new() -> ApnsConfig {
+    ApnsConfig::default()
+  }
+
+  /// Add an APNs header, such as `apns-expiration`.
+  pub fn header(mut self, name: &str, value: &str) -> ApnsConfig {
+    self.headers.insert(name.to_string(), value.to_string());
+    self
+  }
+
+  /// Set the `apns-priority` header: 10 to deliver right away, 5 to save
+  /// power on the device.
+  pub fn priority(self, priority: u8) -> ApnsConfig {
+    self.header("apns-priority", &priority.to_string())
+  }
+
+  /// Set the `apns-collapse-id` header. Notifications with the same id
+  /// replace each other on the device.
+  pub fn collapse_id(self, collapse_id: &str) -> ApnsConfig {
+    self.header("apns-collapse-id", collapse_id)
+  }
+
+  /// Set the whole APNs payload, including the `aps` dictionary and any
+  /// custom keys.
+  pub fn payload(mut self, payload: Value) -> ApnsConfig {
+    self.payload = Some(payload);
+    self
+  }
+
+  /// Set the `aps` dictionary of the payload, keeping any custom keys.
+  pub fn aps(mut self, aps: Value) -> ApnsConfig {
+    let mut payload = match self.payload.take() {
+      Some(Value::Object(payload)) => payload,
+      _ => Map::new()
+    };
+
+    payload.insert("aps".to_string(), aps);
+    self.payload = Some(Value::Object(payload));
+    self
+  }
+
+  /// The APNs headers.
+  pub fn headers(&self) -> &HashMap<String, String> {
+    &self.headers
+  }
+
+  /// The APNs payload, if set.
+  pub fn get_payload(&self) -> Option<&Value> {
+    self.payload.as_ref()
+  }
+}
@@ -0,0 +1,39 @@
+use serde_json;
+use ApnsConfig;
+
+#[test]
+fn should_serialize_empty_apns_config() {
+  let j = serde_json::to_value(&ApnsConfig::new()).unwrap();
+
+  assert_eq!(j, json!({}));
+}
+
+#[test]
+fn should_serialize_apns_headers_and_payload() {
+  let apns = ApnsConfig::new()
+    .priority(10)
+    .collapse_id("score")
+    .header("apns-expiration", "1604750400")
+    .payload(json!({ "aps": { "alert": "India won!", "badge": 1 }, "match_id": 42 }));
+
+  let j = serde_json::to_value(&apns).unwrap();
+
+  assert_eq!(j, json!({
+    "headers": {
+      "apns-priority": "10",
+      "apns-collapse-id": "score",
+      "apns-expiration": "1604750400"
+    },
+    "payload": { "aps": { "alert": "India won!", "badge": 1 }, "match_id": 42 }
+  }));
+}
+
+#[test]
+fn should_set_aps_keeping_custom_keys() {
+  let apns = ApnsConfig::new()
+    .payload(json!({ "match_id": 42 }))
+    .aps(json!({ "content-available": 1 }));
+
+  assert_eq!(apns.get_payload(), Some(&json!({ "match_id": 42, "aps": { "content-available": 1 } })));
+  assert_eq!(ApnsConfig::new().aps(json!({})).get_payload(), Some(&json!({ "aps": {} })));
+}
@@ -0,0 +1,150 @@
+#[cfg(test)]
+mod tests;
+
+use GcmError;
+use serde_json::from_str;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The OAuth2 scope FCM HTTP v1 requires of access tokens.
+pub const FCM_SCOPE: &'static str = "https://www.googleapis.com/auth/firebase.messaging";
+
+/// Supplies OAuth2 access tokens for FCM HTTP v1. `GcmClient` asks for a
+/// token before every request, so implementations should cache tokens and
+/// refresh them before they expire. Mint them with the OAuth2 library of
+/// your choice, from a `ServiceAccount`.
+pub trait TokenProvider: Send + Sync {
+  /// A valid access token, with the `FCM_SCOPE` scope.
+  fn access_token(&self) -> Result<String, GcmError>;
+}
+
+/// How long before it expires `CachingTokenProvider` replaces a token.
+pub const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// An access token fetched beforehand, used as it is. It is never
+/// refreshed, so it only suits short-lived clients.
+#[derive(Clone)]
+pub struct StaticToken {
+  token: String,
+}
+
+impl StaticToken {
+  /// Get a new `StaticToken` for the given access token.
+  pub fn new(token: &str) -> StaticToken {
+    StaticToken { token: token.to_string() }
+  }
+}
+
+impl TokenProvider for StaticToken {
+  fn access_token(&self) -> Result<String, GcmError> {
+    Ok(self.token.clone())
+  }
+}
+
+/// The parts of a service account key file, as downloaded from the Google
+/// Cloud console, needed to send with FCM HTTP v1. This crate doesn't sign
+/// the JWTs that access tokens are exchanged for; pass these to your OAuth2
+/// library. Deliberately not `Debug`, to keep the private key out of logs.
+/// # Examples:
+/// ```no_run
+/// use gcm::{Endpoint, ServiceAccount};
+///
+/// let json = std::fs::read_to_string("service-account.json").unwrap();
+/// let account = ServiceAccount::from_json(&json).unwrap();
+/// let endpoint = Endpoint::FcmV1 { project_id: account.project_id.clone() };
+/// ```
+#[derive(Clone, Deserialize)]
+pub struct ServiceAccount {
+  pub project_id: String,
+  pub client_email: String,
+  pub private_key: String,
+  #[serde(default = "default_token_uri")]
+  pub token_uri: String,
+}
+
+fn default_token_uri() -> String {
+  "https://oauth2.googleapis.com/token".to_string()
+}
+
+impl ServiceAccount {
+  /// Parse a service account key file. Returns `InvalidJsonBody` if it
+  /// isn't one.
+  pub fn from_json(json: &str) -> Result<ServiceAccount, GcmError> {
+    Ok(from_str(json)?)
+  }
+}
+
+/// An access token and how many seconds it is valid for, as in the body of
+/// an OAuth2 token response, which it can be deserialized from.
+#[derive(Clone, Deserialize)]
+pub struct AccessToken {
+  pub access_token: String,
+  pub expires_in: u64,
+}
+
+/// A `TokenProvider` that fetches tokens with the given function, and
+/// reuses each one until `TOKEN_REFRESH_MARGIN` before it expires. Clones
+/// share the cached token, and only one thread fetches at a time.
+/// # Examples:
+/// ```no_run
+/// use gcm::{AccessToken, CachingTokenProvider, GcmClient};
+///
+/// let provider = CachingTokenProvider::new(|| {
+///   // Exchange a signed JWT for a token with your OAuth2 library.
+///   Ok(AccessToken { access_token: "<access token>".to_string(), expires_in: 3600 })
+/// });
+/// let client = GcmClient::fcm_v1("my-project", provider);
+/// ```
+#[derive(Clone)]
+pub struct CachingTokenProvider {
+  fetch: Arc<dyn Fn() -> Result<AccessToken, GcmError> + Send + Sync>,
+  clock: Arc<dyn Fn() -> Instant + Send + Sync>,
+  cached: Arc<Mutex<Option<CachedToken>>>,
+}
+
+struct CachedToken {
+  access_token: String,
+  expires_at: Instant,
+}
+
+impl CachingTokenProvider {
+  /// Get a new `CachingTokenProvider`, which calls `fetch` for a token
+  /// when it has none, or the one it has is about to expire.
+  pub fn new<F>(fetch: F) -> CachingTokenProvider
+      where F: Fn() -> Result<AccessToken, GcmError> + Send + Sync + 'static {
+    CachingTokenProvider {
+      fetch: Arc::new(fetch),
+      clock: Arc::new(Instant::now),
+      cached: Arc::new(Mutex::new(None)),
+    }
+  }
+
+  /// Tell the time with the given function instead of `Instant::now`.
+  /// Mostly useful for tests.
+  pub fn clock<C>(mut self, clock: C) -> CachingTokenProvider
+      where C: Fn() -> Instant + Send + Sync + 'static {
+    self.clock = Arc::new(clock);
+    self
+  }
+}
+
+impl TokenProvider for CachingTokenProvider {
+  fn access_token(&self) -> Result<String, GcmError> {
+    let mut cached = self.cached.lock().unwrap();
+    let now = (self.clock)();
+
+    if let Some(ref token) = *cached {
+      if now + TOKEN_REFRESH_MARGIN < token.expires_at {
+        return Ok(token.access_token.clone());
+      }
+    }
+
+    let token = (self.fetch)()?;
+    *cached = Some(CachedToken {
+      access_token: token.access_token.clone(),
+      expires_at: now + Duration::from_secs(token.expires_in),
+    });
+
+    Ok(token.access_token)
+  }
+}
@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod tests;
+
+use std::time::Duration;
+
+/// Decides how long to wait before retrying a failed send, and when to
+/// give up. See `GcmClient::send_with_retry`.
+pub trait Backoff {
+  /// How long to wait before retry number `attempt`, counting from 0, or
+  /// `None` to stop retrying.
+  fn next_delay(&mut self, attempt: u32) -> Option<Duration>;
+
+  /// The longest to wait when GCM asks for a delay in `Retry-After`; a
+  /// longer one is cut to this. Defaults to a minute.
+  fn max_retry_after(&self) -> Duration {
+    Duration::from_secs(60)
+  }
+}
+
+/// Wait twice as long before each retry: 1, 2, 4... seconds by default,
+/// up to `max_delay`.
+/// # Examples:
+/// ```rust
+/// use gcm::{Backoff, ExponentialBackoff};
+/// use std::time::Duration;
+///
+/// let mut backoff = ExponentialBackoff::new(3)
+///     .initial_delay(Duration::from_millis(500));
+///
+/// assert_eq!(backoff.next_delay(0), Some(Duration::from_millis(500)));
+/// assert_eq!(backoff.next_delay(2), Some(Duration::from_secs(2)));
+/// assert_eq!(backoff.next_delay(3), None);
+/// ```
+#[derive(PartialEq, Debug, Clone)]
+pub struct ExponentialBackoff {
+  max_retries: u32,
+  initial_delay: Duration,
+  max_delay: Duration,
+}
+
+impl ExponentialBackoff {
+  /// Get a new `ExponentialBackoff`, which retries up to `max_retries`
+  /// times, waiting 1 second at first and at most a minute.
+  pub fn new(max_retries: u32) -> ExponentialBackoff {
+    ExponentialBackoff {
+      max_retries: max_retries,
+      initial_delay: Duration::from_secs(1),
+      max_delay: Duration::from_secs(60),
+    }
+  }
+
+  /// How long to wait before the first retry.
+  pub fn initial_delay(mut self, initial_delay: Duration) -> ExponentialBackoff {
+    self.initial_delay = initial_delay;
+    self
+  }
+
+  /// The longest to wait before any retry.
+  pub fn max_delay(mut self, max_delay: Duration) -> ExponentialBackoff {
+    self.max_delay = max_delay;
+    self
+  }
+}
+
+impl Backoff for ExponentialBackoff {
+  fn next_delay(&mut self, attempt: u32) -> Option<Duration> {
+    if attempt >= self.max_retries {
+      return None;
+    }
+
+    let delay = self.initial_delay.checked_mul(1 << attempt.min(31)).unwrap_or(self.max_delay);
+    Some(delay.min(self.max_delay))
+  }
+
+  fn max_retry_after(&self) -> Duration {
+    self.max_delay
+  }
+}
+
+/// Wait the same time before each retry.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ConstantBackoff {
+  delay: Duration,
+  max_retries: u32,
+}
+
+impl ConstantBackoff {
+  /// Get a new `ConstantBackoff`, which retries up to `max_retries`
+  /// times, waiting `delay` before each.
+  pub fn new(delay: Duration, max_retries: u32) -> ConstantBackoff {
+    ConstantBackoff {
+      delay: delay,
+      max_retries: max_retries,
+    }
+  }
+}
+
+impl Backoff for ConstantBackoff {
+  fn next_delay(&mut self, attempt: u32) -> Option<Duration> {
+    if attempt < self.max_retries { Some(self.delay) } else { None }
+  }
+}
@@ -0,0 +1,47 @@
+use {Backoff, ConstantBackoff, ExponentialBackoff};
+use std::time::Duration;
+
+#[test]
+fn should_double_exponential_delays() {
+  let mut backoff = ExponentialBackoff::new(4);
+
+  assert_eq!(backoff.next_delay(0), Some(Duration::from_secs(1)));
+  assert_eq!(backoff.next_delay(1), Some(Duration::from_secs(2)));
+  assert_eq!(backoff.next_delay(2), Some(Duration::from_secs(4)));
+  assert_eq!(backoff.next_delay(3), Some(Duration::from_secs(8)));
+  assert_eq!(backoff.next_delay(4), None);
+}
+
+#[test]
+fn should_cap_exponential_delays() {
+  let mut backoff = ExponentialBackoff::new(100)
+      .initial_delay(Duration::from_millis(100))
+      .max_delay(Duration::from_secs(1));
+
+  assert_eq!(backoff.next_delay(3), Some(Duration::from_millis(800)));
+  assert_eq!(backoff.next_delay(4), Some(Duration::from_secs(1)));
+  assert_eq!(backoff.next_delay(99), Some(Duration::from_secs(1)));
+}
+
+#[test]
+fn should_not_retry_without_max_retries() {
+  assert_eq!(ExponentialBackoff::new(0).next_delay(0), None);
+  assert_eq!(ConstantBackoff::new(Duration::from_secs(1), 0).next_delay(0), None);
+}
+
+#[test]
+fn should_wait_the_same_with_constant_backoff() {
+  let mut backoff = ConstantBackoff::new(Duration::from_millis(250), 2);
+
+  assert_eq!(backoff.next_delay(0), Some(Duration::from_millis(250)));
+  assert_eq!(backoff.next_delay(1), Some(Duration::from_millis(250)));
+  assert_eq!(backoff.next_delay(2), None);
+}
+
+#[test]
+fn should_cap_retry_after_at_max_delay() {
+  let backoff = ExponentialBackoff::new(1).max_delay(Duration::from_secs(5));
+
+  assert_eq!(backoff.max_retry_after(), Duration::from_secs(5));
+  assert_eq!(ConstantBackoff::new(Duration::from_secs(1), 1).max_retry_after(), Duration::from_secs(60));
+}
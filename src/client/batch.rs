@@ -0,0 +1,100 @@
+use transport::TransportResponse;
+
+// A response found in a batch response, with the number of the request it
+// answers, taken from its `Content-ID`, if there is one.
+pub struct BatchPart {
+  pub content_id: Option<usize>,
+  pub response: TransportResponse,
+}
+
+// A boundary that doesn't appear in any of the bodies.
+pub fn boundary(bodies: &[String]) -> String {
+  (0..)
+    .map(|n| format!("gcm_batch_{}", n))
+    .find(|boundary| !bodies.iter().any(|body| body.contains(&boundary[..])))
+    .expect("there is always a boundary left")
+}
+
+// A `multipart/mixed` body with a `POST` to `path` for each of the JSON
+// bodies. The requests are numbered from 1 in their `Content-ID`.
+pub fn encode(boundary: &str, path: &str, bodies: &[String]) -> String {
+  let mut batch = String::new();
+
+  for (index, body) in bodies.iter().enumerate() {
+    batch.push_str(&format!("--{}\r\n", boundary));
+    batch.push_str("Content-Type: application/http\r\n");
+    batch.push_str("Content-Transfer-Encoding: binary\r\n");
+    batch.push_str(&format!("Content-ID: <{}>\r\n\r\n", index + 1));
+    batch.push_str(&format!("POST {} HTTP/1.1\r\n", path));
+    batch.push_str("Content-Type: application/json; charset=utf-8\r\n\r\n");
+    batch.push_str(body);
+    batch.push_str("\r\n");
+  }
+
+  batch.push_str(&format!("--{}--\r\n", boundary));
+  batch
+}
+
+// The responses in a `multipart/mixed` batch response, given its
+// `Content-Type`. `None` if the response isn't one.
+pub fn decode(content_type: &str, body: &str) -> Option<Vec<BatchPart>> {
+  if !content_type.trim_start().to_lowercase().starts_with("multipart/") {
+    return None;
+  }
+
+  let boundary = content_type.split(';')
+    .map(str::trim)
+    .find(|param| param.to_lowercase().starts_with("boundary="))
+    .map(|param| param["boundary=".len()..].trim_matches('"'))?;
+  let delimiter = format!("--{}", boundary);
+
+  body.split(&delimiter[..])
+    .skip(1)
+    .take_while(|part| !part.starts_with("--"))
+    .map(parse_part)
+    .collect()
+}
+
+fn parse_part(part: &str) -> Option<BatchPart> {
+  let (part_headers, http) = split_head(part.trim_start_matches(|c| c == '\r' || c == '\n'))?;
+  let content_id = headers(part_headers).into_iter()
+    .find(|&(ref name, _)| name.eq_ignore_ascii_case("Content-ID"))
+    .and_then(|(_, id)| id.trim_matches(|c| c == '<' || c == '>').trim_start_matches("response-").parse().ok());
+
+  let (head, body) = split_head(http).unwrap_or((http, ""));
+  let mut lines = head.lines();
+  let status = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+
+  Some(BatchPart {
+    content_id: content_id,
+    response: TransportResponse {
+      status: status,
+      headers: headers(&lines.collect::<Vec<_>>().join("\n")),
+      body: body.trim_end_matches(|c| c == '\r' || c == '\n').to_string(),
+      raw_body: None,
+    },
+  })
+}
+
+// Split at the first empty line, which ends a block of headers.
+fn split_head(text: &str) -> Option<(&str, &str)> {
+  let crlf = text.find("\r\n\r\n").map(|index| (index, 4));
+  let lf = text.find("\n\n").map(|index| (index, 2));
+
+  let (index, length) = match (crlf, lf) {
+    (Some(crlf), Some(lf)) => if crlf.0 < lf.0 { crlf } else { lf },
+    (Some(found), None) | (None, Some(found)) => found,
+    (None, None) => return None
+  };
+
+  Some((&text[..index], &text[index + length..]))
+}
+
+fn headers(head: &str) -> Vec<(String, String)> {
+  head.lines()
+    .filter_map(|line| {
+      let colon = line.find(':')?;
+      Some((line[..colon].trim().to_string(), line[colon + 1..].trim().to_string()))
+    })
+    .collect()
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+use std::time::Duration;
+
+use client::GCM_ENDPOINT;
+
+/// How long a `GcmClient` made from the default `GcmConfig` waits for the
+/// server.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The settings of a `GcmClient`, for apps that load their configuration
+/// in one place. Turn it into a client with `GcmClient::from_config`.
+/// # Examples:
+/// ```rust
+/// use gcm::{GcmClient, GcmConfig, FCM_ENDPOINT};
+///
+/// let client = GcmClient::from_config(GcmConfig {
+///   api_key: Some("<FCM Server Key>".to_string()),
+///   endpoint: FCM_ENDPOINT.to_string(),
+///   max_retries: 3,
+///   ..GcmConfig::default()
+/// }).unwrap();
+///
+/// assert_eq!(client.endpoint(), FCM_ENDPOINT);
+/// ```
+#[derive(PartialEq, Clone)]
+pub struct GcmConfig {
+  /// The API key `send_default` sends with.
+  pub api_key: Option<String>,
+  /// The URL messages are sent to. Defaults to `GCM_ENDPOINT`.
+  pub endpoint: String,
+  /// How long to wait for the server, `None` to wait as long as it takes.
+  /// Defaults to `DEFAULT_TIMEOUT`.
+  pub timeout: Option<Duration>,
+  /// How many times to send a message again after a retriable error.
+  /// Defaults to none.
+  pub max_retries: u32,
+}
+
+impl Default for GcmConfig {
+  fn default() -> GcmConfig {
+    GcmConfig {
+      api_key: None,
+      endpoint: GCM_ENDPOINT.to_string(),
+      timeout: Some(DEFAULT_TIMEOUT),
+      max_retries: 0,
+    }
+  }
+}
+
+// The API key is a secret, so `Debug` only tells whether it is set.
+impl fmt::Debug for GcmConfig {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("GcmConfig")
+      .field("api_key", &self.api_key.as_ref().map(|_| "<redacted>"))
+      .field("endpoint", &self.endpoint)
+      .field("timeout", &self.timeout)
+      .field("max_retries", &self.max_retries)
+      .finish()
+  }
+}
@@ -0,0 +1,1055 @@
+#[cfg(test)]
+mod tests;
+mod batch;
+mod config;
+mod request;
+
+pub use self::config::{GcmConfig, DEFAULT_TIMEOUT};
+
+use {Message, Priority, GcmResponse, GcmError, TokenProvider, Backoff, ExponentialBackoff, MAX_REGISTRATION_IDS};
+use message::v1;
+use iid::{self, TokenInfo, TopicResponse, IID_ENDPOINT, MAX_TOPIC_TOKENS};
+use self::request::Request;
+use transport::{Transport, DefaultTransport, TransportError, TransportResponse};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::env;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde_json::{from_str, Value};
+#[cfg(feature = "log")]
+use message::redact_token;
+
+/// The GCM HTTP endpoint. This is what `Message::send` talks to.
+pub const GCM_ENDPOINT: &'static str = "https://gcm-http.googleapis.com/gcm/send";
+
+/// The legacy HTTP endpoint of Firebase Cloud Messaging, which accepts
+/// the same messages as GCM.
+pub const FCM_ENDPOINT: &'static str = "https://fcm.googleapis.com/fcm/send";
+
+/// The services a `GcmClient` can send messages to. Each has its own URL
+/// and way of passing the credential in the `Authorization` header.
+/// # Examples:
+/// ```rust
+/// use gcm::{Endpoint, GcmClient};
+///
+/// let client = GcmClient::for_endpoint(Endpoint::FcmV1 { project_id: "my-project".to_string() });
+///
+/// assert_eq!(client.endpoint(), "https://fcm.googleapis.com/v1/projects/my-project/messages:send");
+/// ```
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Endpoint {
+  /// GCM, at `GCM_ENDPOINT`, with an API key.
+  GcmLegacy,
+  /// The legacy FCM API, at `FCM_ENDPOINT`, with a server key.
+  FcmLegacy,
+  /// FCM HTTP v1, with an OAuth2 access token.
+  FcmV1 { project_id: String },
+}
+
+impl Endpoint {
+  /// The URL messages are sent to.
+  pub fn url(&self) -> String {
+    match *self {
+      Endpoint::GcmLegacy => GCM_ENDPOINT.to_string(),
+      Endpoint::FcmLegacy => FCM_ENDPOINT.to_string(),
+      Endpoint::FcmV1 { ref project_id } =>
+        format!("https://fcm.googleapis.com/v1/projects/{}/messages:send", project_id)
+    }
+  }
+
+  /// The `Authorization` header for the given API key or access token.
+  pub fn authorization(&self, credential: &str) -> String {
+    match *self {
+      Endpoint::GcmLegacy | Endpoint::FcmLegacy => format!("key={}", credential),
+      Endpoint::FcmV1 { .. } => format!("Bearer {}", credential)
+    }
+  }
+}
+
+/// The FCM endpoint for batch requests, used by `GcmClient::send_batch`.
+pub const FCM_BATCH_ENDPOINT: &'static str = "https://fcm.googleapis.com/batch";
+
+/// The most messages FCM accepts in a single batch request.
+/// `send_batch` splits larger lists into batches of this size.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+/// How many chunks `GcmClient::send_multicast` sends at once, unless set
+/// with `multicast_concurrency`.
+pub const DEFAULT_MULTICAST_CONCURRENCY: usize = 4;
+
+/// The environment variable `Message::send_env` reads the API key from.
+pub const API_KEY_VAR: &'static str = "GCM_API_KEY";
+
+/// A value, along with how long it took to get it.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Timed<T> {
+  pub value: T,
+  pub duration: Duration,
+}
+
+/// A client to send messages with. Use this instead of `Message::send`
+/// when you need to talk to an endpoint other than GCM's.
+/// # Examples:
+/// ```no_run
+/// use gcm::{GcmClient, Message, NotificationBuilder, FCM_ENDPOINT};
+///
+/// let notification = NotificationBuilder::new("Hey!").finalize();
+/// let message = Message::new("<registration id>").notification(notification);
+///
+/// let client = GcmClient::with_endpoint(FCM_ENDPOINT).unwrap();
+/// let result = client.send(&message, "<FCM Server Key>");
+/// ```
+///
+/// `GcmClient` is `Send + Sync`, so one client, with its pool of
+/// connections and cached access token, can be shared by a pool of
+/// worker threads through an `Arc`:
+/// ```no_run
+/// use gcm::{GcmClient, Message};
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let client = Arc::new(GcmClient::new());
+///
+/// let workers: Vec<_> = (0..4).map(|_| {
+///   let client = Arc::clone(&client);
+///   thread::spawn(move || {
+///     let message = Message::new("<registration id>").content_available(true);
+///     client.send(&message, "<API key>")
+///   })
+/// }).collect();
+///
+/// for worker in workers {
+///   let result = worker.join().unwrap();
+/// }
+/// ```
+pub struct GcmClient {
+  endpoint: String,
+  // Decides the `Authorization` header. `with_endpoint` keeps the legacy
+  // one, whatever the URL.
+  scheme: Endpoint,
+  batch_endpoint: String,
+  iid_endpoint: String,
+  headers: Vec<(String, String)>,
+  gzip_threshold: Option<usize>,
+  dry_run: Option<bool>,
+  priority: Option<Priority>,
+  api_key: Option<String>,
+  max_retries: u32,
+  multicast_concurrency: usize,
+  default_transport: DefaultTransport,
+  transport: Option<Box<dyn Transport>>,
+  token_provider: Option<Box<dyn TokenProvider>>,
+}
+
+impl GcmClient {
+  /// Get a new instance of `GcmClient`, pointing at `GCM_ENDPOINT`.
+  pub fn new() -> GcmClient {
+    GcmClient {
+      endpoint: GCM_ENDPOINT.to_string(),
+      scheme: Endpoint::GcmLegacy,
+      batch_endpoint: FCM_BATCH_ENDPOINT.to_string(),
+      iid_endpoint: IID_ENDPOINT.to_string(),
+      headers: Vec::new(),
+      gzip_threshold: None,
+      dry_run: None,
+      priority: None,
+      api_key: None,
+      max_retries: 0,
+      multicast_concurrency: DEFAULT_MULTICAST_CONCURRENCY,
+      default_transport: DefaultTransport::new(),
+      transport: None,
+      token_provider: None,
+    }
+  }
+
+  /// Get a new instance of `GcmClient` with the given settings. Returns
+  /// `GcmError::InvalidEndpoint` if the endpoint is empty.
+  pub fn from_config(config: GcmConfig) -> Result<GcmClient, GcmError> {
+    let mut client = GcmClient::with_endpoint(&config.endpoint)?
+      .timeout(config.timeout)
+      .max_retries(config.max_retries);
+    client.api_key = config.api_key;
+    Ok(client)
+  }
+
+  /// Get a new instance of `GcmClient` which sends messages to the given
+  /// URL. Returns `GcmError::InvalidEndpoint` if the URL is empty.
+  pub fn with_endpoint(endpoint: &str) -> Result<GcmClient, GcmError> {
+    let endpoint = endpoint.trim();
+
+    if endpoint.is_empty() {
+      return Err(GcmError::InvalidEndpoint(endpoint.to_string()));
+    }
+
+    let mut client = GcmClient::new();
+    client.endpoint = endpoint.to_string();
+    Ok(client)
+  }
+
+  /// Get a new instance of `GcmClient` which sends messages to the given
+  /// service, authenticating as it expects.
+  pub fn for_endpoint(endpoint: Endpoint) -> GcmClient {
+    let mut client = GcmClient::new();
+    client.endpoint = endpoint.url();
+    client.scheme = endpoint;
+    client
+  }
+
+  /// Get a new instance of `GcmClient` for FCM HTTP v1, which sends
+  /// messages to the given Firebase project with `send_v1`, authenticated
+  /// with access tokens from `token_provider`.
+  pub fn fcm_v1<P: TokenProvider + 'static>(project_id: &str, token_provider: P) -> GcmClient {
+    GcmClient::for_endpoint(Endpoint::FcmV1 { project_id: project_id.to_string() })
+      .token_provider(token_provider)
+  }
+
+  /// The URL that messages are sent to.
+  pub fn endpoint(&self) -> &str {
+    &self.endpoint
+  }
+
+  /// Send batch requests to the given URL instead of
+  /// `FCM_BATCH_ENDPOINT`.
+  pub fn batch_endpoint(mut self, batch_endpoint: &str) -> GcmClient {
+    self.batch_endpoint = batch_endpoint.to_string();
+    self
+  }
+
+  /// Send topic subscriptions to the given URL instead of
+  /// `IID_ENDPOINT`.
+  pub fn iid_endpoint(mut self, iid_endpoint: &str) -> GcmClient {
+    self.iid_endpoint = iid_endpoint.trim_end_matches('/').to_string();
+    self
+  }
+
+  /// Add a header to every request, e.g. `X-Request-ID`. The
+  /// `Authorization` and `Content-Type` headers are set automatically, but
+  /// setting them here overrides them.
+  /// # Examples:
+  /// ```rust
+  /// use gcm::GcmClient;
+  ///
+  /// let client = GcmClient::new()
+  ///     .header("X-Request-ID", "42");
+  /// ```
+  pub fn header(mut self, name: &str, value: &str) -> GcmClient {
+    self.headers.push((name.to_string(), value.to_string()));
+    self
+  }
+
+  /// Gzip request bodies of at least `min_size` bytes, and set the
+  /// `Content-Encoding: gzip` header. Smaller bodies aren't worth it, and
+  /// are sent as they are. Worthwhile for large multicast payloads.
+  /// # Examples:
+  /// ```rust
+  /// use gcm::GcmClient;
+  ///
+  /// let client = GcmClient::new().gzip(1024);
+  /// ```
+  pub fn gzip(mut self, min_size: usize) -> GcmClient {
+    self.gzip_threshold = Some(min_size);
+    self
+  }
+
+  /// Send every message as a dry run, or not, unless the message sets
+  /// `dry_run` itself. Handy for a staging environment.
+  /// # Examples:
+  /// ```rust
+  /// use gcm::GcmClient;
+  ///
+  /// let staging = GcmClient::new().dry_run(true);
+  /// ```
+  pub fn dry_run(mut self, dry_run: bool) -> GcmClient {
+    self.dry_run = Some(dry_run);
+    self
+  }
+
+  /// Send every message with the given priority, unless the message sets
+  /// its own `priority`.
+  /// # Examples:
+  /// ```rust
+  /// use gcm::{GcmClient, Priority};
+  ///
+  /// let client = GcmClient::new().priority(Priority::High);
+  /// ```
+  pub fn priority(mut self, priority: Priority) -> GcmClient {
+    self.priority = Some(priority);
+    self
+  }
+
+  /// Set the API key `send_default` sends with.
+  pub fn api_key(mut self, api_key: &str) -> GcmClient {
+    self.api_key = Some(api_key.to_string());
+    self
+  }
+
+  /// Send a message again, up to `max_retries` times, when it fails with a
+  /// retriable error, like a `ServerError`. Each retry waits for as long
+  /// as GCM asked in `Retry-After`, up to a minute, or else 1, 2, 4...
+  /// seconds; see `send_with_retry` for other policies. Defaults to no
+  /// retries.
+  pub fn max_retries(mut self, max_retries: u32) -> GcmClient {
+    self.max_retries = max_retries;
+    self
+  }
+
+  /// Send at most `concurrency` chunks of a `send_multicast` at once, so
+  /// that a huge list of tokens doesn't start a thread per chunk. Defaults
+  /// to `DEFAULT_MULTICAST_CONCURRENCY` (4).
+  pub fn multicast_concurrency(mut self, concurrency: usize) -> GcmClient {
+    self.multicast_concurrency = concurrency;
+    self
+  }
+
+  /// How long to wait for the server before giving up on a request, with
+  /// an `HttpError`. `None`, the default, waits as long as it takes.
+  pub fn timeout(mut self, timeout: Option<Duration>) -> GcmClient {
+    self.default_transport = self.default_transport.timeout(timeout);
+    self
+  }
+
+  /// Get the access tokens for `send_v1` from the given `TokenProvider`.
+  /// It is asked for a token before every request.
+  pub fn token_provider<P: TokenProvider + 'static>(mut self, token_provider: P) -> GcmClient {
+    self.token_provider = Some(Box::new(token_provider));
+    self
+  }
+
+  /// Send requests through the given `Transport` instead of the
+  /// `DefaultTransport`. Mostly useful for tests, with a `MockTransport`.
+  pub fn transport<T: Transport + 'static>(mut self, transport: T) -> GcmClient {
+    self.transport = Some(Box::new(transport));
+    self
+  }
+
+  /// Accept any TLS certificate from the server, even an invalid or
+  /// self-signed one. This is **dangerous**: anyone on the network can
+  /// then read and change your messages, API key included. Only use it to
+  /// test against a local server. It only affects this client, and has no
+  /// effect once a custom `transport` is set.
+  ///
+  /// To keep it out of production code, it is only available in debug
+  /// builds, or with the `danger-insecure-tls` feature.
+  #[cfg(any(debug_assertions, feature = "danger-insecure-tls"))]
+  pub fn danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> GcmClient {
+    self.default_transport = self.default_transport.danger_accept_invalid_certs(accept_invalid_certs);
+    self
+  }
+
+  /// Trust an additional root certificate, in DER or PEM format, e.g. the
+  /// CA of a TLS-terminating proxy. Returns `TlsInitError` if the
+  /// certificate can't be parsed.
+  /// # Examples:
+  /// ```no_run
+  /// use gcm::GcmClient;
+  ///
+  /// let ca = std::fs::read("proxy-ca.pem").unwrap();
+  /// let client = GcmClient::new()
+  ///     .add_root_certificate(&ca)
+  ///     .unwrap();
+  /// ```
+  pub fn add_root_certificate(mut self, der_or_pem: &[u8]) -> Result<GcmClient, GcmError> {
+    self.default_transport = self.default_transport.add_root_certificate(der_or_pem)?;
+    Ok(self)
+  }
+
+  /// How many idle connections to keep open per host, ready for the next
+  /// request. Defaults to `DEFAULT_POOL_MAX_IDLE_PER_HOST` (5). Raise it
+  /// if you send in bursts from many threads.
+  pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> GcmClient {
+    self.default_transport = self.default_transport.pool_max_idle_per_host(max_idle);
+    self
+  }
+
+  /// How long to keep idle connections open, `None` to keep them as long
+  /// as the server does. Defaults to `DEFAULT_POOL_IDLE_TIMEOUT` (90
+  /// seconds).
+  /// # Examples:
+  /// ```rust
+  /// use gcm::GcmClient;
+  /// use std::time::Duration;
+  ///
+  /// let client = GcmClient::new()
+  ///     .pool_max_idle_per_host(32)
+  ///     .pool_idle_timeout(Some(Duration::from_secs(300)));
+  /// ```
+  pub fn pool_idle_timeout(mut self, idle_timeout: Option<Duration>) -> GcmClient {
+    self.default_transport = self.default_transport.pool_idle_timeout(idle_timeout);
+    self
+  }
+
+  // The custom transport if there is one, the default one otherwise.
+  fn active_transport(&self) -> &dyn Transport {
+    match self.transport {
+      Some(ref transport) => &**transport,
+      None => &self.default_transport
+    }
+  }
+
+  /// Send a message using your API Key. The message is validated first,
+  /// so invalid messages fail without a network call.
+  pub fn send(&self, message: &Message, api_key: &str) -> Result<GcmResponse, GcmError> {
+    self.send_timed(message, api_key).value
+  }
+
+  /// Send a message with the client's API key, set with `api_key` or in
+  /// the `GcmConfig`. Returns `MissingApiKey` if there is none.
+  pub fn send_default(&self, message: &Message) -> Result<GcmResponse, GcmError> {
+    match self.api_key {
+      Some(ref api_key) => self.send(message, api_key),
+      None => Err(GcmError::MissingApiKey("api_key".to_string()))
+    }
+  }
+
+  /// Like `send`, but also returns how long the HTTP request took,
+  /// including connecting and reading the response, and any retries.
+  /// Messages that fail validation are never sent, and take no time.
+  /// # Examples:
+  /// ```no_run
+  /// use gcm::{GcmClient, Message};
+  ///
+  /// let message = Message::new("<registration id>").content_available(true);
+  /// let timed = GcmClient::new().send_timed(&message, "<GCM API Key>");
+  /// println!("took {:?}: {:?}", timed.duration, timed.value.map(|response| response.message_id));
+  /// ```
+  pub fn send_timed(&self, message: &Message, api_key: &str) -> Timed<Result<GcmResponse, GcmError>> {
+    self.send_timed_with(message, api_key, &mut ExponentialBackoff::new(self.max_retries))
+  }
+
+  /// Like `send`, but retries retriable errors, like a `ServerError`, as
+  /// the given `Backoff` decides, in place of the client's `max_retries`.
+  /// When GCM asks for a delay in `Retry-After`, that is waited instead,
+  /// up to `Backoff::max_retry_after`.
+  /// # Examples:
+  /// ```no_run
+  /// use gcm::{ConstantBackoff, GcmClient, Message};
+  /// use std::time::Duration;
+  ///
+  /// let message = Message::new("<registration id>").content_available(true);
+  /// let result = GcmClient::new().send_with_retry(
+  ///   &message, "<GCM API Key>", ConstantBackoff::new(Duration::from_secs(2), 5));
+  /// ```
+  pub fn send_with_retry<B: Backoff>(&self, message: &Message, api_key: &str, mut backoff: B) -> Result<GcmResponse, GcmError> {
+    self.send_timed_with(message, api_key, &mut backoff).value
+  }
+
+  fn send_timed_with(&self, message: &Message, api_key: &str, backoff: &mut dyn Backoff) -> Timed<Result<GcmResponse, GcmError>> {
+    let message = self.with_defaults(message);
+    let message = &*message;
+
+    if let Err(error) = message.validate() {
+      return Timed { value: Err(error), duration: Duration::from_secs(0) };
+    }
+
+    let json_body = match message.to_json() {
+      Ok(body) => body,
+      Err(error) => return Timed { value: Err(error), duration: Duration::from_secs(0) }
+    };
+
+    self.post_message(message, json_body, api_key, backoff)
+  }
+
+  /// Send a message with FCM HTTP v1, using an access token from the
+  /// client's `TokenProvider`; see `fcm_v1`. The message is converted to
+  /// the v1 format: the legacy Android fields, like `collapse_key` and
+  /// `time_to_live`, move to `android` unless the `AndroidConfig` sets
+  /// them, and `dry_run` becomes `validate_only`. v1 sends to one token at
+  /// a time, so messages with `registration_ids` are rejected.
+  ///
+  /// The `message_id` of the response is the `name` FCM gives the message.
+  /// # Examples:
+  /// ```no_run
+  /// use gcm::{GcmClient, Message, NotificationBuilder, StaticToken};
+  ///
+  /// let notification = NotificationBuilder::new("Hey!").finalize();
+  /// let message = Message::new("<registration token>").notification(notification);
+  ///
+  /// let client = GcmClient::fcm_v1("my-project", StaticToken::new("<access token>"));
+  /// let result = client.send_v1(&message);
+  /// ```
+  pub fn send_v1(&self, message: &Message) -> Result<GcmResponse, GcmError> {
+    let message = self.with_defaults(message);
+    let message = &*message;
+
+    message.validate()?;
+    let json_body = v1::body(message)?;
+    let access_token = self.access_token()?;
+
+    let mut backoff = ExponentialBackoff::new(self.max_retries);
+    let response = self.post_message(message, json_body, &access_token, &mut backoff).value?;
+    Ok(with_v1_message_id(response))
+  }
+
+  // An access token from the client's `TokenProvider`, for FCM HTTP v1.
+  fn access_token(&self) -> Result<String, GcmError> {
+    match self.token_provider {
+      Some(ref token_provider) => token_provider.access_token(),
+      None => Err(GcmError::MissingApiKey("token_provider".to_string()))
+    }
+  }
+
+  // Send a serialized message, retrying as `backoff` decides, and time
+  // the requests.
+  #[cfg_attr(not(feature = "log"), allow(unused_variables))]
+  fn post_message(&self, message: &Message, json_body: String, credential: &str, backoff: &mut dyn Backoff)
+      -> Timed<Result<GcmResponse, GcmError>> {
+    #[cfg(feature = "log")]
+    debug!("sending {} bytes to {}", json_body.len(), redacted_target(message));
+
+    #[cfg(feature = "debug_bodies")]
+    let request_body = json_body.clone();
+
+    let request = self.post_request(&self.endpoint, credential);
+    let request = match self.gzip_threshold {
+      Some(min_size) if json_body.len() >= min_size =>
+        request.header("Content-Encoding", "gzip").body(gzip(json_body.as_bytes())),
+      _ => request.body(json_body.into_bytes())
+    };
+
+    let started = Instant::now();
+    let mut attempt = 0;
+    let value = loop {
+      let attempt_started = Instant::now();
+      let result = request.send(self.active_transport());
+
+      #[cfg(feature = "log")]
+      log_outcome(message, &result, attempt_started.elapsed());
+
+      let value = match result {
+        Ok(response) => parse_response(&response),
+        Err(error) => Err(GcmError::from(error))
+      };
+      #[cfg(feature = "debug_bodies")]
+      let value = value.map_err(|error| error.with_request_body(&request_body));
+
+      let delay = match value {
+        Err(ref error) if error.is_retriable() => backoff.next_delay(attempt)
+          .map(|delay| match error.retry_after() {
+            Some(retry_after) => retry_after.min(backoff.max_retry_after()),
+            None => delay
+          }),
+        _ => None
+      };
+
+      match delay {
+        Some(delay) => {
+          thread::sleep(delay);
+          attempt += 1;
+        },
+        None => break value
+      }
+    };
+
+    Timed { value: value, duration: started.elapsed() }
+  }
+
+  /// Send a message using the API Key in the given environment variable,
+  /// such as `API_KEY_VAR`. Returns `MissingApiKey` if it isn't set or is
+  /// empty, without a network call.
+  pub fn send_env(&self, message: &Message, var: &str) -> Result<GcmResponse, GcmError> {
+    match env::var(var) {
+      Ok(ref api_key) if !api_key.is_empty() => self.send(message, api_key),
+      _ => Err(GcmError::MissingApiKey(var.to_string()))
+    }
+  }
+
+  /// Send a large number of messages, starting at most `per_second` of them
+  /// each second and keeping at most `concurrency` requests in flight.
+  /// Messages are taken from the iterator only as they can be sent, and
+  /// `on_result` is called with each result in the order of the messages,
+  /// as soon as it and all the ones before it are done. Returns once every
+  /// message has been sent.
+  ///
+  /// A `per_second` of 0 sends as fast as `concurrency` allows. Requests
+  /// block, so they are made from threads, not futures.
+  ///
+  /// Results are handed to a callback rather than returned as an iterator:
+  /// the transport blocks, so each request needs a thread, and the threads
+  /// borrow the client, the API key and the messages, so they must finish
+  /// before this returns. An iterator would keep them running after it.
+  /// To stop early, end the `messages` iterator; nothing past it is sent,
+  /// and the requests in flight still finish.
+  /// # Examples:
+  /// ```no_run
+  /// use gcm::{GcmClient, Message};
+  ///
+  /// let tokens = vec!["<registration id 1>", "<registration id 2>"];
+  /// let messages = tokens.into_iter().map(|token| Message::new(token).content_available(true));
+  ///
+  /// GcmClient::new().send_stream(messages, "<GCM API Key>", 500, 16, |result| {
+  ///   println!("{:?}", result.map(|response| response.message_id));
+  /// });
+  /// ```
+  pub fn send_stream<'m, I, F>(&self, messages: I, api_key: &str, per_second: u32, concurrency: usize, mut on_result: F)
+      where I: IntoIterator<Item = Message<'m>>, F: FnMut(Result<GcmResponse, GcmError>) {
+    let interval = if per_second == 0 { Duration::from_secs(0) } else { Duration::from_secs(1) / per_second };
+    let concurrency = concurrency.max(1);
+
+    thread::scope(|scope| {
+      let (sender, receiver) = mpsc::channel();
+      let mut pending = BTreeMap::new();
+      let mut next = 0;
+      let mut in_flight = 0;
+      let started = Instant::now();
+
+      for (index, message) in messages.into_iter().enumerate() {
+        while in_flight >= concurrency {
+          let (done, result) = next_result(&receiver);
+          pending.insert(done, result);
+          in_flight -= 1;
+        }
+        emit_in_order(&mut pending, &mut next, &mut on_result);
+
+        let start_at = started + interval * index as u32;
+        if let Some(wait) = start_at.checked_duration_since(Instant::now()) {
+          thread::sleep(wait);
+        }
+
+        // A panic is sent back too, so that it reaches the caller rather
+        // than leaving it waiting for a result that never comes.
+        let sender = sender.clone();
+        scope.spawn(move || {
+          let result = panic::catch_unwind(AssertUnwindSafe(|| self.send(&message, api_key)));
+          let _ = sender.send((index, result));
+        });
+        in_flight += 1;
+      }
+      drop(sender);
+
+      while in_flight > 0 {
+        let (done, result) = next_result(&receiver);
+        pending.insert(done, result);
+        in_flight -= 1;
+        emit_in_order(&mut pending, &mut next, &mut on_result);
+      }
+    });
+  }
+
+  /// Send messages with at most `concurrency` requests in flight, and
+  /// return the results in the order of the messages. Requests block, so
+  /// this uses threads rather than futures; see `send_stream` to also limit
+  /// the rate, or to handle results as they come.
+  /// # Examples:
+  /// ```no_run
+  /// use gcm::{GcmClient, Message};
+  ///
+  /// let messages = vec![
+  ///   Message::new("<registration id 1>").content_available(true),
+  ///   Message::new("<registration id 2>").content_available(true),
+  /// ];
+  /// let results = GcmClient::new().send_all(messages, "<GCM API Key>", 8);
+  /// ```
+  pub fn send_all(&self, messages: Vec<Message>, api_key: &str, concurrency: usize) -> Vec<Result<GcmResponse, GcmError>> {
+    let mut results = Vec::with_capacity(messages.len());
+    self.send_stream(messages, api_key, 0, concurrency, |result| results.push(result));
+    results
+  }
+
+  /// Send many messages in as few requests as possible, by packing up to
+  /// `MAX_BATCH_SIZE` of them in each `multipart/mixed` request to the
+  /// batch endpoint. The batch endpoint only takes FCM HTTP v1 requests,
+  /// so the client must be made with `fcm_v1`, and messages are converted
+  /// as by `send_v1`. Returns `InvalidEndpoint` for other clients, or the
+  /// error of the `TokenProvider`.
+  ///
+  /// Otherwise, returns a result per message, in order. Messages that fail
+  /// validation aren't sent. If a batch request fails as a whole, each of
+  /// its messages fails with the same error.
+  /// # Examples:
+  /// ```no_run
+  /// use gcm::{GcmClient, Message, NotificationBuilder, StaticToken};
+  ///
+  /// let notification = NotificationBuilder::new("Hey!").finalize();
+  /// let messages = vec![
+  ///   Message::new("<registration token 1>").notification(notification.clone()),
+  ///   Message::new("<registration token 2>").notification(notification),
+  /// ];
+  /// let results = GcmClient::fcm_v1("my-project", StaticToken::new("<access token>"))
+  ///     .send_batch(messages);
+  /// ```
+  pub fn send_batch(&self, messages: Vec<Message>) -> Result<Vec<Result<GcmResponse, GcmError>>, GcmError> {
+    match self.scheme {
+      Endpoint::FcmV1 { .. } => (),
+      _ => return Err(GcmError::InvalidEndpoint(self.endpoint.clone()))
+    }
+    let access_token = self.access_token()?;
+
+    Ok(messages.chunks(MAX_BATCH_SIZE)
+      .flat_map(|batch| self.send_batch_request(batch, &access_token))
+      .collect())
+  }
+
+  fn send_batch_request(&self, messages: &[Message], access_token: &str) -> Vec<Result<GcmResponse, GcmError>> {
+    let mut results: Vec<Option<Result<GcmResponse, GcmError>>> = messages.iter().map(|_| None).collect();
+    let mut sent = Vec::new();
+    let mut bodies = Vec::new();
+
+    for (index, message) in messages.iter().enumerate() {
+      let message = self.with_defaults(message);
+      let body = message.validate()
+        .and_then(|_| v1::body(&message));
+
+      match body {
+        Ok(body) => {
+          sent.push(index);
+          bodies.push(body);
+        },
+        Err(error) => results[index] = Some(Err(error))
+      }
+    }
+
+    if !sent.is_empty() {
+      #[cfg(feature = "log")]
+      debug!("sending a batch of {} messages to {}", sent.len(), self.batch_endpoint);
+
+      let boundary = batch::boundary(&bodies);
+      let body = batch::encode(&boundary, self.endpoint_path(), &bodies);
+      let request = self.post_request(&self.batch_endpoint, access_token)
+        .header("Content-Type", &format!("multipart/mixed; boundary={}", boundary))
+        .body(body.into_bytes());
+
+      let mut responses = batch_responses(request.send(self.active_transport()), sent.len());
+
+      for (position, index) in sent.into_iter().enumerate() {
+        let result = responses[position].take().expect("one result per message")
+          .map(with_v1_message_id);
+        #[cfg(feature = "debug_bodies")]
+        let result = result.map_err(|error| error.with_request_body(&bodies[position]));
+        results[index] = Some(result);
+      }
+    }
+
+    results.into_iter()
+      .map(|result| result.expect("one result per message"))
+      .collect()
+  }
+
+  // The path of the endpoint, e.g. `/v1/projects/my-project/messages:send`,
+  // for the requests in a batch.
+  fn endpoint_path(&self) -> &str {
+    let host = self.endpoint.find("://").map_or(0, |index| index + 3);
+
+    self.endpoint[host..].find('/')
+      .map_or("/", |index| &self.endpoint[host + index..])
+  }
+
+  // The message with the client's defaults filled in, where it doesn't set
+  // its own.
+  fn with_defaults<'m, 'a>(&self, message: &'m Message<'a>) -> Cow<'m, Message<'a>> {
+    let mut message = Cow::Borrowed(message);
+
+    if let Some(dry_run) = self.dry_run {
+      if message.get_dry_run().is_none() {
+        message = Cow::Owned(message.into_owned().dry_run(dry_run));
+      }
+    }
+
+    if let Some(priority) = self.priority {
+      if message.get_priority().is_none() {
+        message = Cow::Owned(message.into_owned().priority(priority));
+      }
+    }
+
+    message
+  }
+
+  // A POST to `url` with the default headers, overridden by the custom
+  // ones.
+  fn post_request(&self, url: &str, credential: &str) -> Request {
+    Request::post(url)
+      .header("Authorization", &self.scheme.authorization(credential))
+      .header("Content-Type", "application/json; charset=utf-8")
+      .headers(&self.headers)
+  }
+
+  /// Subscribe registration tokens to a topic, named with or without its
+  /// `/topics/` prefix, with the Instance ID API. The response has a
+  /// result per token, in order. At most `MAX_TOPIC_TOKENS` tokens can be
+  /// subscribed at once.
+  /// # Examples:
+  /// ```no_run
+  /// use gcm::GcmClient;
+  ///
+  /// let response = GcmClient::new()
+  ///     .subscribe_to_topic(vec!["<registration id>"], "news", "<FCM Server Key>")
+  ///     .unwrap();
+  /// println!("failed: {:?}", response.failed_indices());
+  /// ```
+  pub fn subscribe_to_topic(&self, tokens: Vec<&str>, topic: &str, api_key: &str) -> Result<TopicResponse, GcmError> {
+    self.manage_topic("batchAdd", &tokens, topic, api_key)
+  }
+
+  /// Unsubscribe registration tokens from a topic; the counterpart of
+  /// `subscribe_to_topic`.
+  pub fn unsubscribe_from_topic(&self, tokens: Vec<&str>, topic: &str, api_key: &str) -> Result<TopicResponse, GcmError> {
+    self.manage_topic("batchRemove", &tokens, topic, api_key)
+  }
+
+  fn manage_topic(&self, operation: &str, tokens: &[&str], topic: &str, api_key: &str) -> Result<TopicResponse, GcmError> {
+    if tokens.is_empty() || tokens.len() > MAX_TOPIC_TOKENS {
+      return Err(GcmError::InvalidMessage(format!(
+        "topics can be managed for between 1 and {} tokens, got {}", MAX_TOPIC_TOKENS, tokens.len())));
+    }
+
+    let url = format!("{}/iid/v1:{}", self.iid_endpoint, operation);
+    let request = Request::post(&url).header("Content-Type", "application/json; charset=utf-8");
+    let response = self.iid_request(request, api_key)
+      .body(iid::topic_body(tokens, topic).into_bytes())
+      .send(self.active_transport())?;
+
+    iid::parse_response(&response)
+  }
+
+  /// Look up a registration token with the Instance ID API: the app and
+  /// platform it belongs to, and the topics it is subscribed to. A token
+  /// that is no longer valid fails with an error, typically `NotFound` or
+  /// an `InvalidRequest`, which makes this handy to prune dead tokens.
+  /// # Examples:
+  /// ```no_run
+  /// use gcm::GcmClient;
+  ///
+  /// let info = GcmClient::new().token_info("<registration id>", "<FCM Server Key>").unwrap();
+  /// println!("{:?} on {:?}, subscribed to {:?}", info.application, info.platform, info.topics());
+  /// ```
+  pub fn token_info(&self, token: &str, api_key: &str) -> Result<TokenInfo, GcmError> {
+    let url = format!("{}/iid/info/{}?details=true", self.iid_endpoint, token);
+    let response = self.iid_request(Request::get(&url), api_key)
+      .send(self.active_transport())?;
+
+    iid::parse_response(&response)
+  }
+
+  // A request to the Instance ID API, which always takes an API key,
+  // whatever the client's endpoint.
+  fn iid_request(&self, request: Request, api_key: &str) -> Request {
+    request
+      .header("Authorization", &format!("key={}", api_key))
+      .headers(&self.headers)
+  }
+
+  /// Send a message to any number of registration ids. GCM accepts at most
+  /// `MAX_REGISTRATION_IDS` per request, so the ids are split into chunks,
+  /// sent `multicast_concurrency` at a time, and the responses are
+  /// combined: the counts are
+  /// summed, and `results` are in the same order as `tokens`. The combined
+  /// `multicast_id` is that of the first chunk.
+  ///
+  /// If any chunk fails as a whole (say, with a `ServerError`), the error
+  /// of the first failing chunk is returned. The other chunks will still
+  /// have been sent.
+  ///
+  /// The message itself must not have any recipients set.
+  pub fn send_multicast<'a>(&self, message: &Message<'a>, tokens: &[&'a str], api_key: &str)
+      -> Result<GcmResponse, GcmError> {
+    self.send_multicast_with_progress(message, tokens, api_key, |_, _, _| {})
+  }
+
+  /// Like `send_multicast`, but calls `on_chunk` with the index of the
+  /// chunk, the number of chunks, and the response, as each chunk is
+  /// delivered. Chunks are reported in order, and failed chunks aren't
+  /// reported. Handy for a progress bar, or metrics.
+  /// # Examples:
+  /// ```no_run
+  /// use gcm::{GcmClient, Message};
+  ///
+  /// let tokens = vec!["<registration id 1>", "<registration id 2>"];
+  /// let message = Message::new("").content_available(true);
+  /// let result = GcmClient::new().send_multicast_with_progress(&message, &tokens, "<GCM API Key>",
+  ///   |index, total, response| println!("chunk {} of {}: {}", index + 1, total, response));
+  /// ```
+  pub fn send_multicast_with_progress<'a, F>(&self, message: &Message<'a>, tokens: &[&'a str], api_key: &str, mut on_chunk: F)
+      -> Result<GcmResponse, GcmError> where F: FnMut(usize, usize, &GcmResponse) {
+    if tokens.is_empty() {
+      return Err(GcmError::InvalidMessage("no tokens to send to".to_string()));
+    }
+
+    let chunks = tokens.chunks(MAX_REGISTRATION_IDS)
+      .map(|chunk| message.clone().registration_ids(chunk.to_vec()));
+    let total = chunks.len();
+    let mut results = Vec::with_capacity(total);
+
+    self.send_stream(chunks, api_key, 0, self.multicast_concurrency, |result| {
+      if let Ok(ref response) = result {
+        on_chunk(results.len(), total, response);
+      }
+      results.push(result);
+    });
+
+    let responses = results.into_iter().collect::<Result<Vec<_>, _>>()?;
+    Ok(combine(responses))
+  }
+}
+
+// Parse a response, keeping the bytes of a body that couldn't be parsed
+// because it wasn't valid UTF-8, or was cut short.
+fn parse_response(response: &TransportResponse) -> Result<GcmResponse, GcmError> {
+  let result = Message::parse_response(response.status, &response.body, response.get_header("Retry-After"));
+
+  match (result, &response.raw_body) {
+    (Err(GcmError::InvalidJsonBody(_)), &Some(ref raw_body)) =>
+      Err(GcmError::InvalidResponseBody { status: response.status, body: raw_body.clone() }),
+    (result, _) => result
+  }
+}
+
+// v1 answers with the `name` of the message rather than a `message_id`.
+fn with_v1_message_id(mut response: GcmResponse) -> GcmResponse {
+  if response.message_id.is_none() {
+    response.message_id = from_str::<Value>(&response.raw).ok()
+      .and_then(|body| body["name"].as_str().map(str::to_string));
+  }
+  response
+}
+
+// The result of each of the `count` messages in a batch, given the
+// response to the batch request.
+fn batch_responses(result: Result<TransportResponse, TransportError>, count: usize)
+    -> Vec<Option<Result<GcmResponse, GcmError>>> {
+  let response = match result {
+    Ok(response) => response,
+    Err(error) => return (0..count).map(|_| Some(Err(copy_transport_error(&error)))).collect()
+  };
+
+  if response.status != 200 {
+    return (0..count)
+      .map(|_| Some(parse_response(&response)))
+      .collect();
+  }
+
+  let parts = match batch::decode(response.get_header("Content-Type").unwrap_or(""), &response.body) {
+    Some(parts) => parts,
+    None => return (0..count).map(|_| Some(Err(GcmError::InvalidJsonBody(None)))).collect()
+  };
+
+  let mut by_request: Vec<Option<TransportResponse>> = (0..count).map(|_| None).collect();
+  let results = parts.len();
+
+  for (position, part) in parts.into_iter().enumerate() {
+    let index = part.content_id.map_or(position, |id| id.wrapping_sub(1));
+    if index < count {
+      by_request[index] = Some(part.response);
+    }
+  }
+
+  by_request.into_iter()
+    .map(|response| Some(match response {
+      Some(response) => parse_response(&response),
+      None => Err(GcmError::ResultCountMismatch { sent: count, results: results })
+    }))
+    .collect()
+}
+
+// Transport errors can't be cloned, so copy the message.
+fn copy_transport_error(error: &TransportError) -> GcmError {
+  GcmError::from(if error.is_tls() {
+    TransportError::tls(error.to_string())
+  } else {
+    TransportError::new(error.to_string())
+  })
+}
+
+fn gzip(body: &[u8]) -> Vec<u8> {
+  let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+  encoder.write_all(body).expect("writing to a Vec can't fail");
+  encoder.finish().expect("writing to a Vec can't fail")
+}
+
+// The next result a `send_stream` worker sent back, re-raising its panic
+// if it had one.
+fn next_result<T>(receiver: &mpsc::Receiver<(usize, thread::Result<T>)>) -> (usize, T) {
+  match receiver.recv().expect("stream send panicked") {
+    (index, Ok(result)) => (index, result),
+    (_, Err(panic)) => panic::resume_unwind(panic)
+  }
+}
+
+// Hand the results that are next in line to the callback, in order.
+fn emit_in_order<F>(pending: &mut BTreeMap<usize, Result<GcmResponse, GcmError>>, next: &mut usize, on_result: &mut F)
+    where F: FnMut(Result<GcmResponse, GcmError>) {
+  while let Some(result) = pending.remove(next) {
+    on_result(result);
+    *next += 1;
+  }
+}
+
+// Who a message is sent to, fit for logs: registration tokens are
+// shortened, as they identify a device.
+#[cfg(feature = "log")]
+fn redacted_target(message: &Message) -> String {
+  let to = message.get_to();
+
+  if let Some(ids) = message.get_registration_ids() {
+    format!("{} registration ids", ids.len())
+  } else if let Some(condition) = message.get_condition() {
+    format!("condition {}", condition)
+  } else if to.starts_with("/topics/") {
+    to.to_string()
+  } else {
+    format!("token {}", redact_token(to))
+  }
+}
+
+// Never logs the request headers, which hold the API key.
+#[cfg(feature = "log")]
+fn log_outcome(message: &Message, result: &Result<TransportResponse, TransportError>, duration: Duration) {
+  match *result {
+    Ok(ref response) => info!("GCM responded with {} for {} in {:?}",
+      response.status, redacted_target(message), duration),
+    Err(ref error) => warn!("sending to {} failed after {:?}: {}",
+      redacted_target(message), duration, error)
+  }
+}
+
+// Merge the responses of the chunks of a multicast send.
+fn combine(mut responses: Vec<GcmResponse>) -> GcmResponse {
+  if responses.len() == 1 {
+    return responses.remove(0);
+  }
+
+  let raw: Vec<&str> = responses.iter().map(|response| &response.raw[..]).collect();
+  let raw = format!("[{}]", raw.join(","));
+
+  let mut combined = GcmResponse {
+    message_id: None,
+    error: None,
+    multicast_id: None,
+    success: None,
+    failure: None,
+    canonical_ids: None,
+    results: None,
+    raw: raw,
+  };
+
+  for response in responses {
+    combined.multicast_id = combined.multicast_id.or(response.multicast_id);
+    combined.success = sum(combined.success, response.success);
+    combined.failure = sum(combined.failure, response.failure);
+    combined.canonical_ids = sum(combined.canonical_ids, response.canonical_ids);
+
+    if let Some(results) = response.results {
+      combined.results.get_or_insert_with(Vec::new).extend(results);
+    }
+  }
+
+  combined
+}
+
+fn sum(total: Option<u64>, count: Option<u64>) -> Option<u64> {
+  match (total, count) {
+    (None, None) => None,
+    _ => Some(total.unwrap_or(0) + count.unwrap_or(0))
+  }
+}
+
+impl Default for GcmClient {
+  fn default() -> GcmClient {
+    GcmClient::new()
+  }
+}
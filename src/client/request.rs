@@ -0,0 +1,54 @@
+use transport::{Transport, TransportError, TransportResponse};
+
+// A request to one of the endpoints, built up before it is handed to the
+// transport. Headers set later replace earlier ones of the same name, so
+// the client's defaults go first.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Request {
+  pub method: &'static str,
+  pub url: String,
+  pub headers: Vec<(String, String)>,
+  pub body: Vec<u8>,
+}
+
+impl Request {
+  pub fn new(method: &'static str, url: &str) -> Request {
+    Request {
+      method: method,
+      url: url.to_string(),
+      headers: Vec::new(),
+      body: Vec::new(),
+    }
+  }
+
+  pub fn get(url: &str) -> Request {
+    Request::new("GET", url)
+  }
+
+  pub fn post(url: &str) -> Request {
+    Request::new("POST", url)
+  }
+
+  pub fn header(mut self, name: &str, value: &str) -> Request {
+    self.headers.retain(|&(ref header, _)| !header.eq_ignore_ascii_case(name));
+    self.headers.push((name.to_string(), value.to_string()));
+    self
+  }
+
+  pub fn headers(self, headers: &[(String, String)]) -> Request {
+    headers.iter().fold(self, |request, &(ref name, ref value)| request.header(name, value))
+  }
+
+  pub fn body(mut self, body: Vec<u8>) -> Request {
+    self.body = body;
+    self
+  }
+
+  pub fn send(&self, transport: &dyn Transport) -> Result<TransportResponse, TransportError> {
+    match self.method {
+      "GET" => transport.get(&self.url, &self.headers),
+      "POST" => transport.post(&self.url, &self.headers, &self.body),
+      method => Err(TransportError::new(format!("{} isn't supported", method)))
+    }
+  }
+}
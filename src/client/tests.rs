@@ -0,0 +1,1050 @@
+use {GcmClient, GcmError, Message, MockTransport, Transport, TransportError, TransportResponse, GCM_ENDPOINT, FCM_ENDPOINT, FCM_BATCH_ENDPOINT};
+use {Endpoint, Priority, StaticToken, MAX_BATCH_SIZE};
+use {GcmConfig, DEFAULT_TIMEOUT, Backoff, ConstantBackoff, ExponentialBackoff};
+use client::request::Request;
+use std::env;
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
+use flate2::read::GzDecoder;
+use std::error::Error;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use serde_json::{self, Value};
+use std::time::{Duration, Instant};
+
+// A message with the smallest payload `validate` accepts.
+fn message(to: &str) -> Message<'_> {
+  Message::new(to).content_available(true)
+}
+
+#[test]
+fn should_default_to_gcm_endpoint() {
+  let client = GcmClient::new();
+
+  assert_eq!(client.endpoint(), GCM_ENDPOINT);
+}
+
+#[test]
+fn should_set_custom_endpoint() {
+  let client = GcmClient::with_endpoint(FCM_ENDPOINT).unwrap();
+
+  assert_eq!(client.endpoint(), "https://fcm.googleapis.com/fcm/send");
+}
+
+#[test]
+fn should_resolve_endpoint_urls() {
+  assert_eq!(Endpoint::GcmLegacy.url(), GCM_ENDPOINT);
+  assert_eq!(Endpoint::FcmLegacy.url(), FCM_ENDPOINT);
+  assert_eq!(Endpoint::FcmV1 { project_id: "my-project".to_string() }.url(),
+    "https://fcm.googleapis.com/v1/projects/my-project/messages:send");
+}
+
+#[test]
+fn should_resolve_endpoint_authorization() {
+  assert_eq!(Endpoint::GcmLegacy.authorization("key"), "key=key");
+  assert_eq!(Endpoint::FcmLegacy.authorization("key"), "key=key");
+  assert_eq!(Endpoint::FcmV1 { project_id: "my-project".to_string() }.authorization("token"), "Bearer token");
+}
+
+#[test]
+fn should_send_to_endpoint_with_its_authorization() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let endpoint = Endpoint::FcmV1 { project_id: "my-project".to_string() };
+  let client = GcmClient::for_endpoint(endpoint.clone()).transport(transport.clone());
+
+  client.send(&message("token"), "token").unwrap();
+
+  let requests = transport.requests();
+
+  assert_eq!(requests[0].url, endpoint.url());
+  assert_eq!(requests[0].header("Authorization"), Some("Bearer token"));
+
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let client = GcmClient::for_endpoint(Endpoint::FcmLegacy).transport(transport.clone());
+
+  client.send(&message("token"), "key").unwrap();
+
+  assert_eq!(transport.requests()[0].url, FCM_ENDPOINT);
+  assert_eq!(transport.requests()[0].header("Authorization"), Some("key=key"));
+}
+
+#[test]
+fn should_reject_empty_endpoint() {
+  let result = GcmClient::with_endpoint("  ");
+
+  assert!(result.is_err());
+  assert_eq!(result.err().unwrap(), GcmError::InvalidEndpoint("".to_string()));
+}
+
+
+#[test]
+fn should_send_through_transport() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"multicast_id": 1, "success": 1, "failure": 0, "results": [{"message_id": "0:1"}]}"#);
+  let client = GcmClient::with_endpoint(FCM_ENDPOINT).unwrap()
+      .transport(transport.clone());
+
+  let response = client.send(&message("token"), "key").unwrap();
+
+  assert_eq!(response.success, Some(1));
+
+  let requests = transport.requests();
+
+  assert_eq!(requests.len(), 1);
+  assert_eq!(requests[0].url, FCM_ENDPOINT);
+  assert_eq!(requests[0].header("Authorization"), Some("key=key"));
+  assert_eq!(requests[0].header("Content-Type"), Some("application/json; charset=utf-8"));
+  assert_eq!(requests[0].body, br#"{"to":"token","content_available":true}"#.to_vec());
+}
+
+#[test]
+fn should_parse_error_responses_from_transport() {
+  let transport = MockTransport::new()
+      .respond(401, "Unauthorized")
+      .respond_with(TransportResponse::new(503, "").header("Retry-After", "30"));
+  let client = GcmClient::new().transport(transport);
+
+  assert_eq!(client.send(&message("token"), "key").err().unwrap(), GcmError::Unauthorized);
+  assert_eq!(client.send(&message("token"), "key").err().unwrap(),
+    GcmError::ServerError { status: 503, retry_after: Some(Duration::from_secs(30)) });
+}
+
+#[test]
+fn should_report_transport_failures_as_http_errors() {
+  let transport = MockTransport::new()
+      .fail("connection refused");
+  let client = GcmClient::new().transport(transport);
+
+  let error = client.send(&message("token"), "key").err().unwrap();
+
+  assert_eq!(error, GcmError::HttpError(TransportError::new("connection refused")));
+  assert_eq!(error.to_string(), "HttpError: connection refused");
+  assert_eq!(error.source().unwrap().to_string(), "connection refused");
+}
+
+#[test]
+fn should_report_tls_failures_as_tls_init_errors() {
+  let error = GcmError::from(TransportError::tls("no TLS backend"));
+
+  assert_eq!(error, GcmError::TlsInitError(TransportError::tls("no TLS backend")));
+  assert_eq!(error.to_string(), "TlsInitError: no TLS backend");
+  assert_eq!(error.source().unwrap().to_string(), "no TLS backend");
+}
+
+#[test]
+fn should_not_send_invalid_messages() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let client = GcmClient::new().transport(transport.clone());
+
+  assert!(client.send(&message(""), "key").is_err());
+  assert!(transport.requests().is_empty());
+}
+
+#[test]
+fn should_split_multicast_into_chunks() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"multicast_id": 7, "success": 1, "failure": 1, "canonical_ids": 0,
+                       "results": [{"message_id": "0:1"}, {"error": "NotRegistered"}]}"#);
+  let client = GcmClient::new().transport(transport.clone());
+  let ids: Vec<String> = (0..2500).map(|i| format!("id{}", i)).collect();
+  let tokens: Vec<&str> = ids.iter().map(|id| &id[..]).collect();
+
+  let response = client.send_multicast(&message(""), &tokens, "key").unwrap();
+
+  assert_eq!(response.multicast_id, Some(7));
+  assert_eq!(response.success, Some(3));
+  assert_eq!(response.failure, Some(3));
+  assert_eq!(response.canonical_ids, Some(0));
+  assert_eq!(serde_json::from_str::<Value>(&response.raw).unwrap().as_array().unwrap().len(), 3);
+  assert_eq!(response.results.unwrap().len(), 6);
+
+  let mut chunk_sizes: Vec<usize> = transport.requests().iter()
+    .map(|request| {
+      let body: Value = serde_json::from_slice(&request.body).unwrap();
+      body["registration_ids"].as_array().unwrap().len()
+    })
+    .collect();
+  chunk_sizes.sort();
+
+  assert_eq!(chunk_sizes, vec![500, 1000, 1000]);
+}
+
+#[test]
+fn should_report_progress_of_multicast_chunks() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"multicast_id": 7, "success": 1, "failure": 0, "results": [{"message_id": "0:1"}]}"#);
+  let client = GcmClient::new().transport(transport);
+  let ids: Vec<String> = (0..2500).map(|i| format!("id{}", i)).collect();
+  let tokens: Vec<&str> = ids.iter().map(|id| &id[..]).collect();
+  let mut progress = Vec::new();
+
+  let response = client.send_multicast_with_progress(&message(""), &tokens, "key",
+    |index, total, response| progress.push((index, total, response.success))).unwrap();
+
+  assert_eq!(progress, vec![(0, 3, Some(1)), (1, 3, Some(1)), (2, 3, Some(1))]);
+  assert_eq!(response.success, Some(3));
+}
+
+#[test]
+fn should_report_failed_multicast_chunks() {
+  let transport = MockTransport::new()
+      .respond(500, "");
+  let client = GcmClient::new().transport(transport);
+
+  let result = client.send_multicast(&message(""), &["id1", "id2"], "key");
+
+  assert_eq!(result.err().unwrap(), GcmError::ServerError { status: 500, retry_after: None });
+}
+
+#[test]
+fn should_reject_multicast_without_tokens() {
+  let client = GcmClient::new().transport(MockTransport::new());
+
+  assert!(client.send_multicast(&message(""), &[], "key").is_err());
+}
+
+#[test]
+fn should_send_custom_headers() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let client = GcmClient::new()
+      .header("X-Request-ID", "42")
+      .header("authorization", "Bearer token")
+      .transport(transport.clone());
+
+  client.send(&message("token"), "key").unwrap();
+
+  let request = &transport.requests()[0];
+
+  assert_eq!(request.header("X-Request-ID"), Some("42"));
+  assert_eq!(request.header("Authorization"), Some("Bearer token"));
+  assert_eq!(request.header("Content-Type"), Some("application/json; charset=utf-8"));
+  assert_eq!(request.headers.len(), 3);
+}
+#[cfg(feature = "log")]
+#[test]
+fn should_redact_tokens_in_logs() {
+  use client::redacted_target;
+
+  assert_eq!(redacted_target(&Message::new("abcdefghijklmnop")), "token abcdef...");
+  assert_eq!(redacted_target(&Message::new("/topics/cricket")), "/topics/cricket");
+  assert_eq!(redacted_target(&Message::new("").registration_ids(vec!["a", "b"])), "2 registration ids");
+}
+
+#[test]
+fn should_read_api_key_from_env() {
+  env::set_var("GCM_TEST_API_KEY", "key_from_env");
+  let transport = MockTransport::new()
+      .respond(200, r#"{"message_id": "0:1"}"#);
+  let client = GcmClient::new().transport(transport.clone());
+
+  assert!(client.send_env(&message("token"), "GCM_TEST_API_KEY").is_ok());
+  assert_eq!(transport.requests()[0].header("Authorization"), Some("key=key_from_env"));
+}
+
+#[test]
+fn should_fail_without_api_key_in_env() {
+  env::remove_var("GCM_TEST_MISSING_API_KEY");
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let client = GcmClient::new().transport(transport.clone());
+
+  let error = client.send_env(&message("token"), "GCM_TEST_MISSING_API_KEY").err().unwrap();
+
+  assert_eq!(error, GcmError::MissingApiKey("GCM_TEST_MISSING_API_KEY".to_string()));
+  assert_eq!(error.to_string(), "MissingApiKey: GCM_TEST_MISSING_API_KEY is not set");
+  assert!(transport.requests().is_empty());
+}
+
+#[test]
+fn should_stream_results_in_order() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"message_id": "0:1"}"#)
+      .respond(200, r#"{"message_id": "0:2"}"#)
+      .respond(200, r#"{"message_id": "0:3"}"#);
+  let client = GcmClient::new().transport(transport.clone());
+  let messages = vec![message("id1"), message("id2"), message("id3")];
+  let mut ids = Vec::new();
+
+  client.send_stream(messages, "key", 0, 1, |result| ids.push(result.unwrap().message_id.unwrap()));
+
+  assert_eq!(ids, vec!["0:1", "0:2", "0:3"]);
+  assert_eq!(transport.requests().len(), 3);
+}
+
+#[test]
+fn should_stream_every_message_concurrently() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"message_id": "0:1"}"#);
+  let client = GcmClient::new().transport(transport.clone());
+  let ids: Vec<String> = (0..50).map(|i| format!("id{}", i)).collect();
+  let mut results = 0;
+
+  client.send_stream(ids.iter().map(|id| message(id)), "key", 0, 8, |result| {
+    assert!(result.is_ok());
+    results += 1;
+  });
+
+  assert_eq!(results, 50);
+  assert_eq!(transport.requests().len(), 50);
+}
+
+#[test]
+fn should_pace_stream_to_rate_limit() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let client = GcmClient::new().transport(transport);
+  let started = Instant::now();
+
+  client.send_stream(vec![message("id1"), message("id2"), message("id3")], "key", 20, 4, |_| {});
+
+  assert!(started.elapsed() >= Duration::from_millis(100));
+}
+
+#[test]
+fn should_stream_errors_in_place() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let client = GcmClient::new().transport(transport);
+  let mut results = Vec::new();
+
+  client.send_stream(vec![message("id1"), message(""), message("id3")], "key", 0, 2, |result| {
+    results.push(result.is_ok());
+  });
+
+  assert_eq!(results, vec![true, false, true]);
+}
+
+#[cfg(any(debug_assertions, feature = "danger-insecure-tls"))]
+#[test]
+fn should_accept_invalid_certs_only_when_asked() {
+  assert!(!GcmClient::new().default_transport.accepts_invalid_certs());
+  assert!(GcmClient::new().danger_accept_invalid_certs(true).default_transport.accepts_invalid_certs());
+}
+
+#[test]
+fn should_report_invalid_root_certificates_as_tls_init_errors() {
+  match GcmClient::new().add_root_certificate(b"not a certificate") {
+    Err(GcmError::TlsInitError(_)) => {},
+    _ => panic!("expected a TlsInitError")
+  }
+}
+
+#[test]
+fn should_gzip_large_bodies() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let client = GcmClient::new().gzip(16).transport(transport.clone());
+  let message = message("a_token_long_enough_to_compress");
+
+  client.send(&message, "key").unwrap();
+
+  let request = &transport.requests()[0];
+  let mut body = String::new();
+  GzDecoder::new(&request.body[..]).read_to_string(&mut body).unwrap();
+
+  assert_eq!(request.header("Content-Encoding"), Some("gzip"));
+  assert_eq!(body, serde_json::to_string(&message).unwrap());
+}
+
+#[test]
+fn should_not_gzip_small_bodies() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let client = GcmClient::new().gzip(1024).transport(transport.clone());
+
+  client.send(&message("token"), "key").unwrap();
+
+  let request = &transport.requests()[0];
+
+  assert_eq!(request.header("Content-Encoding"), None);
+  assert_eq!(request.body, br#"{"to":"token","content_available":true}"#.to_vec());
+}
+
+#[test]
+fn should_send_all_in_order() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"message_id": "0:1"}"#)
+      .respond(200, r#"{"message_id": "0:2"}"#);
+  let client = GcmClient::new().transport(transport);
+
+  let results = client.send_all(vec![message("id1"), message(""), message("id3")], "key", 1);
+
+  assert_eq!(results.len(), 3);
+  assert_eq!(results[0].as_ref().unwrap().message_id, Some("0:1".to_string()));
+  assert!(results[1].is_err());
+  assert_eq!(results[2].as_ref().unwrap().message_id, Some("0:2".to_string()));
+}
+
+#[test]
+fn should_be_send_and_sync() {
+  fn assert_send_sync<T: Send + Sync>() {}
+
+  assert_send_sync::<GcmClient>();
+}
+
+#[test]
+fn should_share_client_between_threads() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"message_id": "0:1"}"#);
+  let client = Arc::new(GcmClient::new().transport(transport.clone()));
+
+  let workers: Vec<_> = (0..8).map(|_| {
+    let client = Arc::clone(&client);
+    thread::spawn(move || client.send(&message("token"), "key"))
+  }).collect();
+
+  for worker in workers {
+    assert_eq!(worker.join().unwrap().unwrap().message_id, Some("0:1".to_string()));
+  }
+  assert_eq!(transport.requests().len(), 8);
+}
+
+// Counts the requests in flight, keeping the highest count seen.
+#[derive(Clone, Default)]
+struct CountingTransport {
+  in_flight: Arc<AtomicUsize>,
+  max_in_flight: Arc<AtomicUsize>,
+}
+
+impl Transport for CountingTransport {
+  fn post(&self, _: &str, _: &[(String, String)], _: &[u8]) -> Result<TransportResponse, TransportError> {
+    let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+    self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+    thread::sleep(Duration::from_millis(10));
+    self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+    Ok(TransportResponse::new(200, "{}"))
+  }
+}
+
+#[test]
+fn should_bound_send_all_concurrency() {
+  let transport = CountingTransport::default();
+  let client = GcmClient::new().transport(transport.clone());
+  let messages = (0..20).map(|_| message("token")).collect();
+
+  let results = client.send_all(messages, "key", 4);
+
+  assert_eq!(results.len(), 20);
+  assert!(results.iter().all(|result| result.is_ok()));
+  assert!(transport.max_in_flight.load(Ordering::SeqCst) <= 4);
+  assert!(transport.max_in_flight.load(Ordering::SeqCst) > 1);
+}
+
+#[test]
+fn should_bound_multicast_concurrency() {
+  let transport = CountingTransport::default();
+  let client = GcmClient::new()
+      .multicast_concurrency(2)
+      .transport(transport.clone());
+  let ids: Vec<String> = (0..10000).map(|i| format!("id{}", i)).collect();
+  let tokens: Vec<&str> = ids.iter().map(|id| &id[..]).collect();
+
+  assert!(client.send_multicast(&message(""), &tokens, "key").is_ok());
+  assert_eq!(transport.max_in_flight.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn should_bound_multicast_progress_concurrency() {
+  let transport = CountingTransport::default();
+  let client = GcmClient::new()
+      .multicast_concurrency(3)
+      .transport(transport.clone());
+  let ids: Vec<String> = (0..20000).map(|i| format!("id{}", i)).collect();
+  let tokens: Vec<&str> = ids.iter().map(|id| &id[..]).collect();
+  let mut progress = Vec::new();
+
+  client.send_multicast_with_progress(&message(""), &tokens, "key",
+    |index, total, _| progress.push((index, total))).unwrap();
+
+  assert_eq!(progress, (0..20).map(|index| (index, 20)).collect::<Vec<_>>());
+  assert_eq!(transport.max_in_flight.load(Ordering::SeqCst), 3);
+}
+
+struct PanickingTransport;
+
+impl Transport for PanickingTransport {
+  fn post(&self, _: &str, _: &[(String, String)], _: &[u8]) -> Result<TransportResponse, TransportError> {
+    panic!("transport failed");
+  }
+}
+
+#[test]
+fn should_propagate_panics_of_send_all() {
+  let client = GcmClient::new().transport(PanickingTransport);
+  let messages = (0..5).map(|_| message("token")).collect();
+
+  let result = panic::catch_unwind(AssertUnwindSafe(|| client.send_all(messages, "key", 2)));
+
+  assert_eq!(result.err().unwrap().downcast_ref::<&str>(), Some(&"transport failed"));
+}
+
+#[test]
+fn should_propagate_panics_of_send_multicast() {
+  let client = GcmClient::new().transport(PanickingTransport);
+
+  let result = panic::catch_unwind(AssertUnwindSafe(|| client.send_multicast(&message(""), &["id1", "id2"], "key")));
+
+  assert!(result.is_err());
+}
+
+#[test]
+fn should_time_sends() {
+  let client = GcmClient::new().transport(CountingTransport::default());
+
+  let timed = client.send_timed(&message("token"), "key");
+
+  assert!(timed.value.is_ok());
+  assert!(timed.duration >= Duration::from_millis(10));
+}
+
+#[test]
+fn should_not_time_invalid_messages() {
+  let client = GcmClient::new().transport(CountingTransport::default());
+
+  let timed = client.send_timed(&message(""), "key");
+
+  assert!(timed.value.is_err());
+  assert_eq!(timed.duration, Duration::from_secs(0));
+}
+
+#[test]
+fn should_apply_client_dry_run_to_messages_without_one() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let client = GcmClient::new()
+      .dry_run(true)
+      .transport(transport.clone());
+
+  client.send(&message("token"), "key").unwrap();
+  client.send(&message("token").dry_run(false), "key").unwrap();
+
+  let requests = transport.requests();
+
+  assert_eq!(requests[0].body, br#"{"to":"token","content_available":true,"dry_run":true}"#.to_vec());
+  assert_eq!(requests[1].body, br#"{"to":"token","content_available":true,"dry_run":false}"#.to_vec());
+}
+
+#[test]
+fn should_not_set_dry_run_without_a_client_default() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let client = GcmClient::new().transport(transport.clone());
+
+  client.send(&message("token"), "key").unwrap();
+  client.send(&message("token").dry_run(true), "key").unwrap();
+
+  let requests = transport.requests();
+
+  assert_eq!(requests[0].body, br#"{"to":"token","content_available":true}"#.to_vec());
+  assert_eq!(requests[1].body, br#"{"to":"token","content_available":true,"dry_run":true}"#.to_vec());
+}
+
+#[test]
+fn should_apply_client_priority_to_messages_without_one() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let client = GcmClient::new()
+      .priority(Priority::High)
+      .transport(transport.clone());
+
+  client.send(&message("token"), "key").unwrap();
+  client.send(&message("token").priority(Priority::Normal), "key").unwrap();
+  client.send(&message("token").high_priority(), "key").unwrap();
+
+  let requests = transport.requests();
+
+  assert_eq!(requests[0].body, br#"{"to":"token","priority":"high","content_available":true}"#.to_vec());
+  assert_eq!(requests[1].body, br#"{"to":"token","priority":"normal","content_available":true}"#.to_vec());
+  assert_eq!(requests[2].body, br#"{"to":"token","priority":"high","content_available":true}"#.to_vec());
+}
+
+#[test]
+fn should_not_set_priority_without_a_client_default() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let client = GcmClient::new().transport(transport.clone());
+
+  client.send(&message("token"), "key").unwrap();
+  client.send(&message("token").high_priority(), "key").unwrap();
+
+  let requests = transport.requests();
+
+  assert_eq!(requests[0].body, br#"{"to":"token","content_available":true}"#.to_vec());
+  assert_eq!(requests[1].body, br#"{"to":"token","priority":"high","content_available":true}"#.to_vec());
+}
+
+#[test]
+fn should_return_invalid_response_body_with_the_bytes_received() {
+  let transport = MockTransport::new()
+      .respond_bytes(200, b"{\"message_id\": \"0:\xff");
+  let client = GcmClient::new().transport(transport);
+
+  let error = client.send(&message("token"), "key").err().unwrap();
+
+  assert_eq!(error, GcmError::InvalidResponseBody { status: 200, body: b"{\"message_id\": \"0:\xff".to_vec() });
+  assert_eq!(error.status(), Some(200));
+  assert_eq!(error.to_string(), "InvalidResponseBody: 19 bytes with status 200");
+}
+
+#[test]
+fn should_decode_bodies_that_are_not_utf8_lossily() {
+  let transport = MockTransport::new()
+      .respond_bytes(400, b"bad \xff request");
+  let client = GcmClient::new().transport(transport);
+
+  let error = client.send(&message("token"), "key").err().unwrap();
+
+  match error {
+    GcmError::BadRequest { status, body, .. } => assert_eq!((status, &body[..]), (400, "bad \u{fffd} request")),
+    error => panic!("expected a BadRequest, got {:?}", error)
+  }
+}
+
+#[test]
+fn should_default_config() {
+  let config = GcmConfig::default();
+
+  assert_eq!(config.api_key, None);
+  assert_eq!(config.endpoint, GCM_ENDPOINT);
+  assert_eq!(config.timeout, Some(DEFAULT_TIMEOUT));
+  assert_eq!(config.max_retries, 0);
+}
+
+#[test]
+fn should_create_client_from_config() {
+  let transport = MockTransport::new()
+      .respond_with(TransportResponse::new(503, "").header("Retry-After", "0"))
+      .respond(200, r#"{"message_id": "0:1"}"#);
+  let client = GcmClient::from_config(GcmConfig {
+    api_key: Some("config key".to_string()),
+    endpoint: FCM_ENDPOINT.to_string(),
+    timeout: Some(Duration::from_secs(5)),
+    max_retries: 1,
+  }).unwrap().transport(transport.clone());
+
+  let response = client.send_default(&message("token")).unwrap();
+
+  assert_eq!(client.endpoint(), FCM_ENDPOINT);
+  assert_eq!(response.message_id, Some("0:1".to_string()));
+
+  let requests = transport.requests();
+
+  assert_eq!(requests.len(), 2);
+  assert_eq!(requests[1].url, FCM_ENDPOINT);
+  assert_eq!(requests[1].header("Authorization"), Some("key=config key"));
+}
+
+#[test]
+fn should_reject_config_without_endpoint() {
+  let config = GcmConfig { endpoint: " ".to_string(), ..GcmConfig::default() };
+
+  assert!(GcmClient::from_config(config).is_err());
+}
+
+#[test]
+fn should_not_log_config_api_key() {
+  let config = GcmConfig { api_key: Some("secret".to_string()), ..GcmConfig::default() };
+
+  assert!(!format!("{:?}", config).contains("secret"));
+}
+
+#[test]
+fn should_require_api_key_for_send_default() {
+  let client = GcmClient::new().transport(MockTransport::new().respond(200, "{}"));
+
+  assert_eq!(client.send_default(&message("token")).err().unwrap(),
+    GcmError::MissingApiKey("api_key".to_string()));
+}
+
+#[test]
+fn should_not_retry_by_default() {
+  let transport = MockTransport::new()
+      .respond_with(TransportResponse::new(429, "").header("Retry-After", "0"))
+      .respond(200, "{}");
+  let client = GcmClient::new().transport(transport.clone());
+
+  let error = client.send(&message("token"), "key").err().unwrap();
+
+  assert_eq!(error, GcmError::RateLimited { retry_after: Some(Duration::from_secs(0)) });
+  assert_eq!(transport.requests().len(), 1);
+}
+
+#[test]
+fn should_stop_retrying_after_max_retries() {
+  let transport = MockTransport::new()
+      .respond_with(TransportResponse::new(500, "").header("Retry-After", "0"));
+  let client = GcmClient::new()
+      .max_retries(2)
+      .transport(transport.clone());
+
+  assert!(client.send(&message("token"), "key").is_err());
+  assert_eq!(transport.requests().len(), 3);
+}
+
+#[test]
+fn should_cap_retry_after_at_the_backoff_max() {
+  let transport = MockTransport::new()
+      .respond_with(TransportResponse::new(503, "").header("Retry-After", "999999999"))
+      .respond(200, r#"{"message_id": "0:1"}"#);
+  let client = GcmClient::new().transport(transport.clone());
+  let backoff = ExponentialBackoff::new(1).max_delay(Duration::from_millis(10));
+
+  let started = Instant::now();
+  let response = client.send_with_retry(&message("token"), "key", backoff).unwrap();
+
+  assert!(started.elapsed() < Duration::from_secs(5));
+  assert_eq!(response.message_id, Some("0:1".to_string()));
+  assert_eq!(transport.requests().len(), 2);
+}
+
+#[test]
+fn should_not_retry_permanent_errors() {
+  let transport = MockTransport::new()
+      .respond(401, "");
+  let client = GcmClient::new()
+      .max_retries(2)
+      .transport(transport.clone());
+
+  assert_eq!(client.send(&message("token"), "key").err().unwrap(), GcmError::Unauthorized);
+  assert_eq!(transport.requests().len(), 1);
+}
+
+// Retries right away, and records the attempts it was asked about.
+struct RecordingBackoff {
+  attempts: Vec<u32>,
+  max_retries: u32,
+}
+
+impl<'a> Backoff for &'a mut RecordingBackoff {
+  fn next_delay(&mut self, attempt: u32) -> Option<Duration> {
+    self.attempts.push(attempt);
+    if attempt < self.max_retries { Some(Duration::from_secs(0)) } else { None }
+  }
+}
+
+#[test]
+fn should_retry_as_the_backoff_decides() {
+  let transport = MockTransport::new()
+      .respond(503, "")
+      .respond(500, "")
+      .respond(200, r#"{"message_id": "0:1"}"#);
+  let client = GcmClient::new().transport(transport.clone());
+  let mut backoff = RecordingBackoff { attempts: Vec::new(), max_retries: 5 };
+
+  let response = client.send_with_retry(&message("token"), "key", &mut backoff).unwrap();
+
+  assert_eq!(response.message_id, Some("0:1".to_string()));
+  assert_eq!(backoff.attempts, vec![0, 1]);
+  assert_eq!(transport.requests().len(), 3);
+}
+
+#[test]
+fn should_stop_when_the_backoff_gives_up() {
+  let transport = MockTransport::new()
+      .respond(503, "");
+  let client = GcmClient::new().transport(transport.clone());
+  let mut backoff = RecordingBackoff { attempts: Vec::new(), max_retries: 1 };
+
+  let error = client.send_with_retry(&message("token"), "key", &mut backoff).err().unwrap();
+
+  assert_eq!(error.status(), Some(503));
+  assert_eq!(backoff.attempts, vec![0, 1]);
+  assert_eq!(transport.requests().len(), 2);
+}
+
+#[test]
+fn should_retry_with_constant_backoff() {
+  let transport = MockTransport::new()
+      .respond(503, "")
+      .respond(200, "{}");
+  let client = GcmClient::new().transport(transport.clone());
+
+  assert!(client.send_with_retry(&message("token"), "key", ConstantBackoff::new(Duration::from_millis(1), 1)).is_ok());
+  assert_eq!(transport.requests().len(), 2);
+}
+
+#[test]
+fn should_replace_request_headers_of_the_same_name() {
+  let request = Request::post("https://example.com/send")
+      .header("Content-Type", "application/json")
+      .header("Authorization", "key=a")
+      .headers(&[("content-type".to_string(), "text/plain".to_string())]);
+
+  assert_eq!(request.headers, vec![
+    ("Authorization".to_string(), "key=a".to_string()),
+    ("content-type".to_string(), "text/plain".to_string()),
+  ]);
+}
+
+#[test]
+fn should_send_request_through_transport() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+
+  let response = Request::post("https://example.com/send")
+      .header("Authorization", "key=a")
+      .body(b"{}".to_vec())
+      .send(&transport)
+      .unwrap();
+
+  let requests = transport.requests();
+
+  assert_eq!(response.status, 200);
+  assert_eq!(requests[0].method, "POST");
+  assert_eq!(requests[0].url, "https://example.com/send");
+  assert_eq!(requests[0].header("Authorization"), Some("key=a"));
+  assert_eq!(requests[0].body, b"{}".to_vec());
+
+  Request::new("GET", "https://example.com/info").send(&transport).unwrap();
+
+  assert_eq!(transport.requests()[1].method, "GET");
+  assert_eq!(transport.requests()[1].body, Vec::<u8>::new());
+}
+
+#[test]
+fn should_fail_requests_a_transport_does_not_support() {
+  let transport = CountingTransport::default();
+
+  assert!(Request::new("GET", "https://example.com").send(&transport).is_err());
+  assert!(Request::new("DELETE", "https://example.com").send(&MockTransport::new().respond(200, "")).is_err());
+}
+
+const BATCH_RESPONSE: &'static str = "--batch_abc\r\n\
+Content-Type: application/http\r\n\
+Content-ID: <response-2>\r\n\
+\r\n\
+HTTP/1.1 404 Not Found\r\n\
+Content-Type: application/json; charset=UTF-8\r\n\
+\r\n\
+{\"error\": {\"code\": 404, \"status\": \"NOT_FOUND\"}}\r\n\
+--batch_abc\r\n\
+Content-Type: application/http\r\n\
+Content-ID: <response-1>\r\n\
+\r\n\
+HTTP/1.1 200 OK\r\n\
+Content-Type: application/json; charset=UTF-8\r\n\
+\r\n\
+{\"name\": \"projects/my-project/messages/0:1\"}\r\n\
+--batch_abc--\r\n";
+
+fn v1_client(transport: &MockTransport) -> GcmClient {
+  GcmClient::fcm_v1("my-project", StaticToken::new("ya29.token"))
+    .transport(transport.clone())
+}
+
+#[test]
+fn should_send_messages_in_a_batch() {
+  let transport = MockTransport::new()
+      .respond_with(TransportResponse::new(200, BATCH_RESPONSE)
+        .header("Content-Type", "multipart/mixed; boundary=batch_abc"));
+  let client = v1_client(&transport);
+
+  let results = client.send_batch(vec![message("token1"), message(""), message("token2")]).unwrap();
+
+  assert_eq!(results.len(), 3);
+  assert_eq!(results[0].as_ref().unwrap().message_id, Some("projects/my-project/messages/0:1".to_string()));
+  assert!(results[1].is_err());
+  assert_eq!(results[2].as_ref().err().unwrap(), &GcmError::NotFound);
+
+  let requests = transport.requests();
+
+  assert_eq!(requests.len(), 1);
+  assert_eq!(requests[0].url, FCM_BATCH_ENDPOINT);
+  assert_eq!(requests[0].header("Authorization"), Some("Bearer ya29.token"));
+  assert_eq!(requests[0].header("Content-Type"), Some("multipart/mixed; boundary=gcm_batch_0"));
+  assert_eq!(String::from_utf8(requests[0].body.clone()).unwrap(), "--gcm_batch_0\r\n\
+Content-Type: application/http\r\n\
+Content-Transfer-Encoding: binary\r\n\
+Content-ID: <1>\r\n\
+\r\n\
+POST /v1/projects/my-project/messages:send HTTP/1.1\r\n\
+Content-Type: application/json; charset=utf-8\r\n\
+\r\n\
+{\"message\":{\"token\":\"token1\"}}\r\n\
+--gcm_batch_0\r\n\
+Content-Type: application/http\r\n\
+Content-Transfer-Encoding: binary\r\n\
+Content-ID: <2>\r\n\
+\r\n\
+POST /v1/projects/my-project/messages:send HTTP/1.1\r\n\
+Content-Type: application/json; charset=utf-8\r\n\
+\r\n\
+{\"message\":{\"token\":\"token2\"}}\r\n\
+--gcm_batch_0--\r\n");
+}
+
+#[test]
+fn should_reject_batches_for_legacy_endpoints() {
+  let transport = MockTransport::new();
+  let client = GcmClient::with_endpoint(FCM_ENDPOINT).unwrap().transport(transport.clone());
+
+  assert_eq!(client.send_batch(vec![message("token")]).err().unwrap(),
+    GcmError::InvalidEndpoint(FCM_ENDPOINT.to_string()));
+  assert!(transport.requests().is_empty());
+}
+
+#[test]
+fn should_split_batches_at_the_limit() {
+  let transport = MockTransport::new()
+      .respond(500, "");
+  let client = v1_client(&transport);
+  let messages: Vec<Message> = (0..MAX_BATCH_SIZE + 1).map(|_| message("token")).collect();
+
+  let results = client.send_batch(messages).unwrap();
+
+  assert_eq!(results.len(), MAX_BATCH_SIZE + 1);
+  assert!(results.iter().all(|result| result.as_ref().err().unwrap().status() == Some(500)));
+  assert_eq!(transport.requests().len(), 2);
+}
+
+#[test]
+fn should_fail_batch_messages_without_a_response() {
+  let transport = MockTransport::new()
+      .respond_with(TransportResponse::new(200, "--batch_abc--\r\n")
+        .header("Content-Type", "multipart/mixed; boundary=batch_abc"));
+  let client = v1_client(&transport);
+
+  let results = client.send_batch(vec![message("token")]).unwrap();
+
+  assert_eq!(results[0].as_ref().err().unwrap(), &GcmError::ResultCountMismatch { sent: 1, results: 0 });
+}
+
+#[test]
+fn should_send_with_fcm_v1() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"name": "projects/my-project/messages/0:1"}"#);
+  let client = GcmClient::fcm_v1("my-project", StaticToken::new("ya29.token"))
+      .transport(transport.clone());
+
+  let response = client.send_v1(&message("token")).unwrap();
+
+  assert_eq!(response.message_id, Some("projects/my-project/messages/0:1".to_string()));
+
+  let requests = transport.requests();
+
+  assert_eq!(requests[0].url, "https://fcm.googleapis.com/v1/projects/my-project/messages:send");
+  assert_eq!(requests[0].header("Authorization"), Some("Bearer ya29.token"));
+  assert_eq!(requests[0].body, br#"{"message":{"token":"token"}}"#.to_vec());
+}
+
+#[test]
+fn should_require_a_token_provider_for_fcm_v1() {
+  let transport = MockTransport::new();
+  let client = GcmClient::for_endpoint(Endpoint::FcmV1 { project_id: "my-project".to_string() })
+      .transport(transport.clone());
+
+  assert_eq!(client.send_v1(&message("token")).err().unwrap(),
+    GcmError::MissingApiKey("token_provider".to_string()));
+  assert!(transport.requests().is_empty());
+}
+
+#[test]
+fn should_subscribe_tokens_to_topic() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"results": [{}, {"error": "NOT_FOUND"}]}"#);
+  let client = GcmClient::new().transport(transport.clone());
+
+  let response = client.subscribe_to_topic(vec!["token1", "token2"], "news", "api_key").unwrap();
+
+  assert!(!response.is_success());
+  assert_eq!(response.failed_indices(), vec![1]);
+
+  let requests = transport.requests();
+  let body: Value = serde_json::from_slice(&requests[0].body).unwrap();
+
+  assert_eq!(requests[0].url, "https://iid.googleapis.com/iid/v1:batchAdd");
+  assert_eq!(requests[0].header("Authorization"), Some("key=api_key"));
+  assert_eq!(body, json!({"to": "/topics/news", "registration_tokens": ["token1", "token2"]}));
+}
+
+#[test]
+fn should_unsubscribe_tokens_from_topic() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"results": [{}]}"#);
+  let client = GcmClient::new()
+      .iid_endpoint("http://localhost:8080/")
+      .transport(transport.clone());
+
+  let response = client.unsubscribe_from_topic(vec!["token"], "/topics/news", "api_key").unwrap();
+
+  assert!(response.is_success());
+  assert_eq!(transport.requests()[0].url, "http://localhost:8080/iid/v1:batchRemove");
+}
+
+#[test]
+fn should_not_manage_topics_without_tokens() {
+  let transport = MockTransport::new();
+  let client = GcmClient::new().transport(transport.clone());
+
+  match client.subscribe_to_topic(vec![], "news", "api_key") {
+    Err(GcmError::InvalidMessage(_)) => (),
+    result => panic!("expected InvalidMessage, got {:?}", result)
+  }
+  assert!(transport.requests().is_empty());
+}
+
+#[test]
+fn should_look_up_token_info() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"application": "com.iid.example", "platform": "ANDROID", "rel": {"topics": {"news": {"addDate": "2015-07-30"}}}}"#);
+  let client = GcmClient::new().transport(transport.clone());
+
+  let info = client.token_info("token", "api_key").unwrap();
+
+  assert_eq!(info.platform, Some("ANDROID".to_string()));
+  assert_eq!(info.topics(), vec!["news"]);
+
+  let requests = transport.requests();
+
+  assert_eq!(requests[0].method, "GET");
+  assert_eq!(requests[0].url, "https://iid.googleapis.com/iid/info/token?details=true");
+  assert_eq!(requests[0].header("Authorization"), Some("key=api_key"));
+}
+
+#[cfg(feature = "debug_bodies")]
+#[test]
+fn should_attach_request_body_to_rejections() {
+  let transport = MockTransport::new()
+      .respond(400, r#"{"error": "InvalidParameters"}"#)
+      .respond(403, "Forbidden");
+  let client = GcmClient::new().transport(transport);
+  let message = message("token").collapse_key("update");
+
+  let invalid = client.send(&message, "secret_key").err().unwrap();
+  let forbidden = client.send(&message, "secret_key").err().unwrap();
+
+  assert_eq!(invalid.request_body(), Some(r#"{"to":"token","collapse_key":"update","content_available":true}"#));
+  assert_eq!(forbidden.request_body(), invalid.request_body());
+  assert!(!invalid.request_body().unwrap().contains("secret_key"));
+}
+
+#[cfg(not(feature = "debug_bodies"))]
+#[test]
+fn should_not_keep_request_body_by_default() {
+  let transport = MockTransport::new()
+      .respond(400, r#"{"error": "InvalidParameters"}"#);
+  let client = GcmClient::new().transport(transport);
+
+  let error = client.send(&message("token"), "key").err().unwrap();
+
+  assert_eq!(error.status(), Some(400));
+  assert_eq!(error.request_body(), None);
+}
+
+#[test]
+fn should_not_send_empty_messages() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"message_id": "0:1"}"#);
+  let client = GcmClient::new().transport(transport.clone());
+
+  assert_eq!(client.send(&Message::new("token"), "key").err().unwrap(),
+    GcmError::InvalidMessage(
+      "one of `notification`, `data`, `content_available`, `webpush`, `apns` or `android` must be set".to_string()));
+  assert!(transport.requests().is_empty());
+}
@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod tests;
+
+use GcmError;
+use message::status_error;
+use transport::TransportResponse;
+use serde::de::DeserializeOwned;
+use serde_json::from_str;
+use std::collections::HashMap;
+
+/// The Instance ID server API, which manages the topics of registration
+/// tokens. See `GcmClient::subscribe_to_topic`.
+pub const IID_ENDPOINT: &'static str = "https://iid.googleapis.com";
+
+/// The most tokens the Instance ID API (un)subscribes in one request.
+pub const MAX_TOPIC_TOKENS: usize = 1000;
+
+/// The result of subscribing or unsubscribing one token.
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+pub struct TopicResult {
+  /// Why the token couldn't be (un)subscribed, like `NOT_FOUND` or
+  /// `INVALID_ARGUMENT`. `None` if it was.
+  pub error: Option<String>,
+}
+
+/// The response to `subscribe_to_topic` or `unsubscribe_from_topic`, with
+/// a result per token, in the order they were given.
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+pub struct TopicResponse {
+  #[serde(default)]
+  pub results: Vec<TopicResult>,
+}
+
+impl TopicResponse {
+  /// Whether every token was (un)subscribed.
+  pub fn is_success(&self) -> bool {
+    self.results.iter().all(|result| result.error.is_none())
+  }
+
+  /// The positions in `results` of the tokens that failed.
+  pub fn failed_indices(&self) -> Vec<usize> {
+    self.results.iter()
+      .enumerate()
+      .filter(|&(_, result)| result.error.is_some())
+      .map(|(index, _)| index)
+      .collect()
+  }
+}
+
+// The body of a batchAdd or batchRemove request. `topic` may be given
+// with or without its `/topics/` prefix.
+pub(crate) fn topic_body(tokens: &[&str], topic: &str) -> String {
+  json!({
+    "to": format!("/topics/{}", topic.trim_start_matches("/topics/")),
+    "registration_tokens": tokens,
+  }).to_string()
+}
+
+/// What the Instance ID API knows of a registration token. See
+/// `GcmClient::token_info`.
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenInfo {
+  /// The package name or bundle id of the app, e.g. `com.example.app`.
+  pub application: Option<String>,
+  /// The sender id, i.e. the project number, the token was created for.
+  pub authorized_entity: Option<String>,
+  /// `ANDROID`, `IOS` or `CHROME`.
+  pub platform: Option<String>,
+  pub application_version: Option<String>,
+  /// The SHA-1 fingerprint of the certificate the app is signed with.
+  pub app_signer: Option<String>,
+  /// Whether an Android device is `ROOTED` or `NOT_ROOTED`, if known.
+  pub attest_status: Option<String>,
+  /// `WIFI`, `MOBILE` or `OTHER`, if the device is connected.
+  pub connection_type: Option<String>,
+  /// The day the device last connected, e.g. `2015-05-12`.
+  pub connect_date: Option<String>,
+  /// The topics the token is subscribed to, if any.
+  pub rel: Option<TokenRelations>,
+}
+
+impl TokenInfo {
+  /// The names of the topics the token is subscribed to, sorted.
+  pub fn topics(&self) -> Vec<&str> {
+    let mut topics: Vec<&str> = self.rel.iter()
+      .flat_map(|rel| rel.topics.keys())
+      .map(|topic| &topic[..])
+      .collect();
+    topics.sort();
+    topics
+  }
+}
+
+/// The relations of a token, in the `rel` field of its `TokenInfo`.
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+pub struct TokenRelations {
+  /// The topics the token is subscribed to, by name.
+  #[serde(default)]
+  pub topics: HashMap<String, TopicSubscription>,
+}
+
+/// A topic subscription of a token.
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicSubscription {
+  /// The day the token was subscribed, e.g. `2015-07-30`.
+  pub add_date: Option<String>,
+}
+
+// Parse a response of the Instance ID API, which reports errors the way
+// GCM does.
+pub(crate) fn parse_response<T: DeserializeOwned>(response: &TransportResponse) -> Result<T, GcmError> {
+  if response.status != 200 {
+    return Err(status_error(response.status, &response.body, response.get_header("Retry-After")));
+  }
+
+  Ok(from_str(&response.body)?)
+}
@@ -0,0 +1,66 @@
+use {GcmError, TokenInfo, TopicResponse, TopicResult, TransportResponse};
+use iid::{parse_response, topic_body};
+use serde_json::{self, Value};
+
+#[test]
+fn should_add_topics_prefix_once() {
+  let with_prefix: Value = serde_json::from_str(&topic_body(&["token"], "/topics/news")).unwrap();
+  let without_prefix: Value = serde_json::from_str(&topic_body(&["token"], "news")).unwrap();
+
+  assert_eq!(with_prefix, json!({"to": "/topics/news", "registration_tokens": ["token"]}));
+  assert_eq!(without_prefix, with_prefix);
+}
+
+#[test]
+fn should_parse_topic_results() {
+  let response: TopicResponse = parse_response(&TransportResponse::new(200,
+    r#"{"results": [{}, {"error": "INVALID_ARGUMENT"}, {}]}"#)).unwrap();
+
+  assert_eq!(response.results[1], TopicResult { error: Some("INVALID_ARGUMENT".to_string()) });
+  assert_eq!(response.failed_indices(), vec![1]);
+}
+
+#[test]
+fn should_parse_topic_errors() {
+  assert_eq!(parse_response::<TopicResponse>(&TransportResponse::new(401, "")).err().unwrap(), GcmError::Unauthorized);
+
+  match parse_response::<TopicResponse>(&TransportResponse::new(400, r#"{"error": "InvalidToken"}"#)) {
+    Err(GcmError::InvalidRequest(error)) => assert_eq!(error.code, "InvalidToken"),
+    result => panic!("expected InvalidRequest, got {:?}", result)
+  }
+}
+
+#[test]
+fn should_parse_token_info() {
+  let info: TokenInfo = parse_response(&TransportResponse::new(200, r#"{
+    "application": "com.iid.example",
+    "authorizedEntity": "123456782354",
+    "platform": "ANDROID",
+    "attestStatus": "ROOTED",
+    "appSigner": "1a2bc3d4e5",
+    "connectionType": "WIFI",
+    "connectDate": "2015-05-12",
+    "rel": {
+      "topics": {
+        "topicname1": {"addDate": "2015-07-30"},
+        "topicname2": {"addDate": "2015-07-30"}
+      }
+    }
+  }"#)).unwrap();
+
+  assert_eq!(info.application, Some("com.iid.example".to_string()));
+  assert_eq!(info.authorized_entity, Some("123456782354".to_string()));
+  assert_eq!(info.platform, Some("ANDROID".to_string()));
+  assert_eq!(info.connect_date, Some("2015-05-12".to_string()));
+  assert_eq!(info.topics(), vec!["topicname1", "topicname2"]);
+  assert_eq!(info.rel.unwrap().topics["topicname1"].add_date, Some("2015-07-30".to_string()));
+}
+
+#[test]
+fn should_parse_token_info_without_topics() {
+  let info: TokenInfo = parse_response(&TransportResponse::new(200,
+    r#"{"application": "com.iid.example", "platform": "IOS"}"#)).unwrap();
+
+  assert_eq!(info.rel, None);
+  assert!(info.topics().is_empty());
+}
@@ -73,12 +73,38 @@ mod message;
 pub use message::*;
 mod notification;
 pub use notification::*;
+mod client;
+pub use client::*;
+mod topic;
+pub use topic::*;
+mod webpush;
+pub use webpush::*;
+mod apns;
+pub use apns::*;
+mod android;
+pub use android::*;
+mod transport;
+pub use transport::*;
+mod auth;
+pub use auth::*;
+mod backoff;
+pub use backoff::*;
+mod iid;
+pub use iid::*;
 
 pub use message::response::GcmError as Error;
 
+extern crate flate2;
 extern crate hyper;
 extern crate hyper_native_tls;
+extern crate native_tls;
+#[cfg(feature = "reqwest")]
+extern crate reqwest;
+#[cfg(feature = "log")]
+#[macro_use]
+extern crate log;
 extern crate serde;
+#[macro_use]
 extern crate serde_json;
 
 #[macro_use]
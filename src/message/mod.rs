@@ -1,23 +1,48 @@
 #[cfg(test)]
 mod tests;
 pub mod response;
+mod retry_after;
+mod owned;
+pub(crate) mod v1;
 
 pub use message::response::*;
-use notification::Notification;
+pub use message::owned::*;
+use client::{GcmClient, API_KEY_VAR};
+use notification::{self, Notification};
+use webpush::WebpushConfig;
+use apns::ApnsConfig;
+use android::AndroidConfig;
+use topic;
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::str;
-use std::io::Read;
-
-use hyper::Client;
-use hyper::header;
-use hyper::mime::{Mime, TopLevel, SubLevel, Attr, Value};
-use hyper::status::{StatusCode,StatusClass};
-use hyper::net::HttpsConnector;
-use hyper_native_tls::NativeTlsClient;
-use serde_json::{from_str, to_string};
-use serde::{Serializer};
-
-#[derive(PartialEq, Debug, Serialize)]
+use std::fmt;
+
+use serde_json::{from_str, to_string, to_value, Value};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error as DeError;
+
+/// The largest payload, in bytes, GCM accepts for a message.
+pub const MAX_PAYLOAD_SIZE: usize = 4096;
+
+/// The largest payload, in bytes, GCM accepts for a message sent to a topic.
+pub const MAX_TOPIC_PAYLOAD_SIZE: usize = 2048;
+
+/// The most registration ids GCM accepts in a single message.
+/// `send_multicast` splits larger lists into chunks of this size.
+pub const MAX_REGISTRATION_IDS: usize = 1000;
+
+/// The longest `time_to_live`, in seconds, GCM accepts: 4 weeks.
+pub const MAX_TIME_TO_LIVE: i32 = 2_419_200;
+
+/// The longest `collapse_key`, in bytes, `validate` accepts. GCM doesn't
+/// document a limit, but a longer key is almost certainly a mistake. Note
+/// that only 4 distinct collapse keys are kept per device at a time.
+pub const MAX_COLLAPSE_KEY_LENGTH: usize = 64;
+
+/// The longest analytics label FCM accepts, in characters.
+pub const MAX_ANALYTICS_LABEL_LENGTH: usize = 50;
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize)]
 pub enum Priority {
   Normal,
   High
@@ -31,12 +56,15 @@ pub enum Priority {
 /// 
 /// let message = Message::new("<registration id>").dry_run(true);
 /// ```
-#[derive(Serialize)]
+#[derive(Clone, Serialize)]
 pub struct Message<'a> {
-  to: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  to: Option<Cow<'a, str>>,
   #[serde(skip_serializing_if = "Option::is_none")]
   registration_ids: Option<Vec<String>>,
   #[serde(skip_serializing_if = "Option::is_none")]
+  condition: Option<&'a str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   collapse_key: Option<&'a str>,
   #[serde(skip_serializing_if = "Option::is_none", serialize_with = "priority_lowercase")]
   priority: Option<Priority>,
@@ -50,10 +78,107 @@ pub struct Message<'a> {
   restricted_package_name: Option<&'a str>,
   #[serde(skip_serializing_if = "Option::is_none")]
   dry_run: Option<bool>,
-  #[serde(skip_serializing_if = "Option::is_none")]
-  data: Option<HashMap<String, String>>,
+  #[serde(skip_serializing_if = "is_empty_data")]
+  data: Option<HashMap<String, Value>>,
   #[serde(skip_serializing_if = "Option::is_none")]
   notification: Option<Notification<'a>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  webpush: Option<WebpushConfig>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  apns: Option<ApnsConfig>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  android: Option<AndroidConfig>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  fcm_options: Option<FcmOptions>,
+}
+
+// FCM's options for all platforms, sent as `fcm_options`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FcmOptions {
+  analytics_label: String,
+}
+
+// Accepts what `priority_lowercase` writes, ignoring case.
+impl<'de> Deserialize<'de> for Priority {
+  fn deserialize<D>(deserializer: D) -> Result<Priority, D::Error>
+      where D: Deserializer<'de> {
+    let priority = String::deserialize(deserializer)?;
+
+    match &priority.to_lowercase()[..] {
+      "normal" => Ok(Priority::Normal),
+      "high" => Ok(Priority::High),
+      _ => Err(D::Error::unknown_variant(&priority, &["normal", "high"]))
+    }
+  }
+}
+
+// Registration tokens identify a device, so `Debug` shortens them.
+// Topics and conditions are shown as they are, and the API key is never
+// part of a message.
+impl<'a> fmt::Debug for Message<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("Message")
+      .field("to", &redact_token(self.get_to()))
+      .field("registration_ids", &self.registration_ids.as_ref()
+        .map(|ids| ids.iter().map(|id| redact_token(id)).collect::<Vec<_>>()))
+      .field("condition", &self.condition)
+      .field("collapse_key", &self.collapse_key)
+      .field("priority", &self.priority)
+      .field("content_available", &self.content_available)
+      .field("delay_while_idle", &self.delay_while_idle)
+      .field("time_to_live", &self.time_to_live)
+      .field("restricted_package_name", &self.restricted_package_name)
+      .field("dry_run", &self.dry_run)
+      .field("data", &self.data)
+      .field("notification", &self.notification)
+      .field("webpush", &self.webpush)
+      .field("apns", &self.apns)
+      .field("android", &self.android)
+      .field("fcm_options", &self.fcm_options)
+      .finish()
+  }
+}
+
+// The first few characters of a registration token, e.g. `APA91b...`.
+// Topics are left as they are.
+pub(crate) fn redact_token(token: &str) -> String {
+  if token.is_empty() || token.starts_with("/topics/") {
+    token.to_string()
+  } else {
+    format!("{}...", token.chars().take(6).collect::<String>())
+  }
+}
+
+// GCM reserves `from`, `message_type` and `notification`, and anything
+// starting with `google` or `gcm`.
+fn is_reserved_data_key(key: &str) -> bool {
+  match key {
+    "from" | "message_type" | "notification" => true,
+    _ => key.starts_with("google") || key.starts_with("gcm")
+  }
+}
+
+// Analytics labels are 1 to 50 characters out of `[a-zA-Z0-9-_.~%]`.
+fn is_analytics_label(label: &str) -> bool {
+  !label.is_empty()
+    && label.chars().count() <= MAX_ANALYTICS_LABEL_LENGTH
+    && label.chars().all(|c| c.is_ascii_alphanumeric() || "-_.~%".contains(c))
+}
+
+// Android package names are at least two dot separated segments, each
+// starting with a letter, and made of letters, digits and underscores.
+fn is_package_name(name: &str) -> bool {
+  let segments: Vec<&str> = name.split('.').collect();
+
+  segments.len() >= 2 && segments.iter().all(|segment| {
+    segment.starts_with(|c: char| c.is_ascii_alphabetic())
+      && segment.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+  })
+}
+
+// An empty `data` map is left out, as if it weren't set.
+fn is_empty_data(data: &Option<HashMap<String, Value>>) -> bool {
+  data.as_ref().map_or(true, HashMap::is_empty)
 }
 
 fn priority_lowercase<S>(priority_field: &Option<Priority>, serializer: S) -> Result<S::Ok, S::Error>
@@ -70,11 +195,13 @@ fn priority_lowercase<S>(priority_field: &Option<Priority>, serializer: S) -> Re
 
 impl <'a> Message<'a> {
   /// Get a new instance of Message. You need to supply either
-  /// a registration id, or a topic (/topic/...).
+  /// a registration id, or a topic (/topic/...). An empty `to` leaves the
+  /// recipient unset, for multicast and condition messages.
   pub fn new(to: &'a str) -> Message {
     Message {
-      to: to,
+      to: if to.is_empty() { None } else { Some(Cow::Borrowed(to)) },
       registration_ids: None,
+      condition: None,
       collapse_key: None,
       priority: None,
       content_available: None,
@@ -84,16 +211,82 @@ impl <'a> Message<'a> {
       dry_run: None,
       data: None,
       notification: None,
+      webpush: None,
+      apns: None,
+      android: None,
+      fcm_options: None,
+    }
+  }
+
+  /// Get a new instance of Message, to be sent to every device subscribed
+  /// to the named topic. The name is given without the `/topics/` prefix,
+  /// and may only contain letters, digits and `-_.~%`; other names are
+  /// rejected with `InvalidMessage`.
+  /// # Examples:
+  /// ```rust
+  /// use gcm::Message;
+  ///
+  /// let message = Message::to_topic("cricket").unwrap();
+  ///
+  /// assert_eq!(message.get_to(), "/topics/cricket");
+  /// ```
+  pub fn to_topic(name: &str) -> Result<Message<'a>, GcmError> {
+    if !topic::is_valid_name(name) {
+      return Err(GcmError::InvalidMessage(format!("invalid topic name `{}`", name)));
     }
+
+    let mut message = Message::new("");
+    message.to = Some(Cow::Owned(format!("/topics/{}", name)));
+    Ok(message)
+  }
+
+  /// Get a new instance of Message, to be sent to each of the given
+  /// registration ids, and without a single recipient in `to`.
+  /// # Examples:
+  /// ```rust
+  /// use gcm::Message;
+  ///
+  /// let message = Message::multicast(vec!["<id 1>", "<id 2>"]);
+  ///
+  /// assert_eq!(message.get_to(), "");
+  /// ```
+  pub fn multicast(ids: Vec<&'a str>) -> Message<'a> {
+    Message::new("").registration_ids(ids)
   }
 
   /// Set various registration ids to which the message ought to be sent.
+  /// A multicast message has no single recipient, so create it with
+  /// `multicast`, or with an empty `to`:
+  /// ```rust
+  /// use gcm::Message;
+  ///
+  /// let message = Message::new("").registration_ids(vec!["<id 1>", "<id 2>"]);
+  /// ```
   pub fn registration_ids(mut self, ids: Vec<&'a str>) -> Message<'a> {
     self.registration_ids = Some(ids.iter().map(|s| s.to_string()).collect());
     self
   }
 
+  /// Send the message to every device subscribed to a combination of
+  /// topics. Like with `registration_ids`, leave `to` empty. Use `Topic` to
+  /// build the condition.
+  /// # Examples:
+  /// ```rust
+  /// use gcm::{Message, Topic};
+  ///
+  /// let condition = Topic::new("India").unwrap()
+  ///     .and(Topic::new("Cricket").unwrap())
+  ///     .to_string();
+  /// let message = Message::new("").condition(&condition);
+  /// ```
+  pub fn condition(mut self, condition: &'a str) -> Message<'a> {
+    self.condition = Some(condition);
+    self
+  }
+
   /// Set this parameter to identify groups of messages that can be collapsed.
+  /// Empty keys, or keys longer than `MAX_COLLAPSE_KEY_LENGTH`, fail
+  /// validation.
   pub fn collapse_key(mut self, collapse_key: &'a str) -> Message<'a> {
     self.collapse_key = Some(collapse_key);
     self
@@ -112,7 +305,23 @@ impl <'a> Message<'a> {
     self
   }
 
-  /// To set the `content-available` field on iOS
+  /// Shorthand for `priority(Priority::High)`, which overrides a client's
+  /// default priority.
+  /// # Examples:
+  /// ```rust
+  /// use gcm::{Message, Priority};
+  ///
+  /// let message = Message::new("<registration id>").high_priority();
+  ///
+  /// assert_eq!(message.get_priority(), Some(Priority::High));
+  /// ```
+  pub fn high_priority(self) -> Message<'a> {
+    self.priority(Priority::High)
+  }
+
+  /// To set the `content-available` field on iOS, which wakes the app up
+  /// to handle the message in the background. Android ignores it. It is
+  /// only sent when set.
   pub fn content_available(mut self, content_available: bool) -> Message<'a> {
     self.content_available = Some(content_available);
     self
@@ -125,13 +334,15 @@ impl <'a> Message<'a> {
   }
 
   /// How long (in seconds) to keep the message on GCM servers in case the device 
-  /// is offline. The maximum and default is 4 weeks.
+  /// is offline. The maximum and default is 4 weeks (`MAX_TIME_TO_LIVE`);
+  /// values outside `0..=MAX_TIME_TO_LIVE` fail validation.
   pub fn time_to_live(mut self, time_to_live: i32) -> Message<'a> {
     self.time_to_live = Some(time_to_live);
     self
   }
 
   /// Package name of the application where the registration tokens must match.
+  /// Names that aren't valid Android package names fail validation.
   pub fn restricted_package_name(mut self, restricted_package_name: &'a str) -> Message<'a> {
     self.restricted_package_name = Some(restricted_package_name);
     self
@@ -144,7 +355,8 @@ impl <'a> Message<'a> {
   }
 
   /// Use this to add custom key-value pairs to the message. This data
-  /// must be handled appropriately on the client end.
+  /// must be handled appropriately on the client end. An empty map isn't
+  /// sent at all.
   /// # Examples:
   /// ```rust
   /// use gcm::Message;
@@ -156,15 +368,63 @@ impl <'a> Message<'a> {
   /// let message = Message::new("<registration id>").data(map);
   /// ```
   pub fn data(mut self, data: HashMap<&'a str, &'a str>) -> Message<'a> {
-    let mut datamap: HashMap<String, String> = HashMap::new();
+    let mut datamap: HashMap<String, Value> = HashMap::new();
     for (key, val) in data.iter() {
-      datamap.insert(key.to_string(), val.to_string());
+      datamap.insert(key.to_string(), Value::String(val.to_string()));
     }
 
     self.data = Some(datamap);
     self
   }
 
+  /// Like `data`, but the values can be any JSON: numbers, booleans,
+  /// arrays or nested objects.
+  /// # Examples:
+  /// ```rust
+  /// # #[macro_use] extern crate serde_json;
+  /// # extern crate gcm;
+  /// # fn main() {
+  /// use gcm::Message;
+  /// use std::collections::HashMap;
+  ///
+  /// let mut map = HashMap::new();
+  /// map.insert("score".to_string(), json!(42));
+  /// map.insert("teams".to_string(), json!({ "home": "India", "away": "Australia" }));
+  ///
+  /// let message = Message::new("<registration id>").data_json(map);
+  /// # }
+  /// ```
+  pub fn data_json(mut self, data: HashMap<String, Value>) -> Message<'a> {
+    self.data = Some(data);
+    self
+  }
+
+  /// Like `data_json`, but takes any struct that serializes to a JSON
+  /// object, with its fields as the keys. Returns `InvalidJsonBody` if it
+  /// doesn't serialize to an object.
+  /// # Examples:
+  /// ```rust
+  /// # #[macro_use] extern crate serde_derive;
+  /// # extern crate gcm;
+  /// # fn main() {
+  /// use gcm::Message;
+  ///
+  /// #[derive(Serialize)]
+  /// struct Score { home: u32, away: u32 }
+  ///
+  /// let message = Message::new("<registration id>")
+  ///     .data_struct(Score { home: 250, away: 248 })
+  ///     .unwrap();
+  /// # }
+  /// ```
+  pub fn data_struct<T: Serialize>(self, data: T) -> Result<Message<'a>, GcmError> {
+    match to_value(data) {
+      Ok(Value::Object(map)) => Ok(self.data_json(map.into_iter().collect())),
+      Ok(_) => Err(GcmError::InvalidJsonBody(None)),
+      Err(error) => Err(error.into())
+    }
+  }
+
   /// Use this to set a `Notification` for the message.
   /// # Examples:
   /// ```rust
@@ -182,6 +442,118 @@ impl <'a> Message<'a> {
     self
   }
 
+  /// Set options for browsers receiving the message through Web Push.
+  /// Only FCM understands these.
+  pub fn webpush(mut self, webpush: WebpushConfig) -> Message<'a> {
+    self.webpush = Some(webpush);
+    self
+  }
+
+  /// Set options for iOS devices receiving the message through APNs.
+  /// Only FCM understands these.
+  pub fn apns(mut self, apns: ApnsConfig) -> Message<'a> {
+    self.apns = Some(apns);
+    self
+  }
+
+  /// Set options for Android devices, in the form FCM HTTP v1 expects.
+  /// The legacy GCM and FCM endpoints read the flat fields instead, such
+  /// as `collapse_key` and `priority`.
+  pub fn android(mut self, android: AndroidConfig) -> Message<'a> {
+    self.android = Some(android);
+    self
+  }
+
+  /// Set a label for the message's analytics data in the Firebase
+  /// console, e.g. to track a campaign. Only FCM understands this; `validate`
+  /// checks it is 1 to 50 letters, digits, `-`, `_`, `.`, `~` or `%`.
+  pub fn analytics_label(mut self, analytics_label: &str) -> Message<'a> {
+    self.fcm_options = Some(FcmOptions { analytics_label: analytics_label.to_string() });
+    self
+  }
+
+  /// The recipient set with `new`; empty for multicast and condition
+  /// messages. The getters are prefixed with `get_` since the plain names
+  /// are taken by the setters.
+  pub fn get_to(&self) -> &str {
+    self.to.as_ref().map_or("", |to| &to[..])
+  }
+
+  /// The registration ids the message will be sent to, if any.
+  pub fn get_registration_ids(&self) -> Option<&[String]> {
+    self.registration_ids.as_ref().map(|ids| &ids[..])
+  }
+
+  /// The topic condition the message will be sent to, if any.
+  pub fn get_condition(&self) -> Option<&str> {
+    self.condition
+  }
+
+  /// The collapse key, if any.
+  pub fn get_collapse_key(&self) -> Option<&str> {
+    self.collapse_key
+  }
+
+  /// The priority, if set.
+  pub fn get_priority(&self) -> Option<Priority> {
+    self.priority
+  }
+
+  /// The `content-available` flag, if set.
+  pub fn get_content_available(&self) -> Option<bool> {
+    self.content_available
+  }
+
+  /// The `delay_while_idle` flag, if set.
+  pub fn get_delay_while_idle(&self) -> Option<bool> {
+    self.delay_while_idle
+  }
+
+  /// The time to live in seconds, if set.
+  pub fn get_time_to_live(&self) -> Option<i32> {
+    self.time_to_live
+  }
+
+  /// The restricted package name, if set.
+  pub fn get_restricted_package_name(&self) -> Option<&str> {
+    self.restricted_package_name
+  }
+
+  /// The `dry_run` flag, if set.
+  pub fn get_dry_run(&self) -> Option<bool> {
+    self.dry_run
+  }
+
+  /// The custom data, if any.
+  pub fn get_data(&self) -> Option<&HashMap<String, Value>> {
+    self.data.as_ref()
+  }
+
+  /// The notification, if one was attached.
+  pub fn get_notification(&self) -> Option<&Notification<'a>> {
+    self.notification.as_ref()
+  }
+
+  /// The Web Push options, if set.
+  pub fn get_webpush(&self) -> Option<&WebpushConfig> {
+    self.webpush.as_ref()
+  }
+
+  /// The APNs options, if set.
+  pub fn get_apns(&self) -> Option<&ApnsConfig> {
+    self.apns.as_ref()
+  }
+
+  /// The Android options, if set.
+  pub fn get_android(&self) -> Option<&AndroidConfig> {
+    self.android.as_ref()
+  }
+
+  /// The analytics label, if set.
+  pub fn get_analytics_label(&self) -> Option<&str> {
+    self.fcm_options.as_ref().map(|fcm_options| &fcm_options.analytics_label[..])
+  }
+
   /// Send the message using your GCM API Key.
   /// # Examples:
   /// ```no_run
@@ -196,58 +568,228 @@ impl <'a> Message<'a> {
   ///     .send("<GCM API Key>");
   /// ```
   pub fn send(self, api_key: &'a str) -> Result<GcmResponse, GcmError> {
-  	let ssl = NativeTlsClient::new().unwrap();
-  	let connector = HttpsConnector::new(ssl);
-  	let client = Client::with_connector(connector);
-    let json_body;
-
-    match to_string(&self) {
-      Ok(body) => {json_body = body;},
-      Err(_) => {return Err(GcmError::InvalidJsonBody);}
-    };
-
-  	let result = client.post("https://gcm-http.googleapis.com/gcm/send")
-  					.body(json_body.as_bytes())
-  					.header(header::Authorization("key=".to_string() + api_key))
-  					.header(
-              header::ContentType(
-                Mime(
-                  TopLevel::Application,
-                  SubLevel::Json,
-                  vec![(Attr::Charset, Value::Utf8)]
-                )
-              )
-            )
-  					.send();
-
-    match result {
-      Ok(mut res) => {
-        let mut body = String::new();
-        match res.read_to_string(&mut body) {
-          Ok(_) => Message::parse_response(res.status, &body),
-          Err(_) => Message::parse_response(StatusCode::InternalServerError, "Server Error")
-        }
-      },
-      Err(_) => {
-        Message::parse_response(StatusCode::InternalServerError, "Server Error")
+    self.send_ref(api_key)
+  }
+
+  /// Like `send`, but borrows the message, so it can be logged or sent
+  /// again afterwards, e.g. to another endpoint with a `GcmClient`.
+  /// # Examples:
+  /// ```no_run
+  /// use gcm::{GcmClient, Message, FCM_ENDPOINT};
+  ///
+  /// let message = Message::new("<registration id>").content_available(true);
+  ///
+  /// if message.send_ref("<GCM API Key>").is_err() {
+  ///   let fallback = GcmClient::with_endpoint(FCM_ENDPOINT).unwrap();
+  ///   let result = fallback.send(&message, "<FCM Server Key>");
+  /// }
+  /// ```
+  pub fn send_ref(&self, api_key: &str) -> Result<GcmResponse, GcmError> {
+    GcmClient::new().send(self, api_key)
+  }
+
+  /// Send the message using the API Key in the `GCM_API_KEY` environment
+  /// variable (`API_KEY_VAR`). Returns `MissingApiKey` if it isn't set.
+  pub fn send_env(self) -> Result<GcmResponse, GcmError> {
+    GcmClient::new().send_env(&self, API_KEY_VAR)
+  }
+
+  /// Send the message to any number of registration ids. The ids are sent
+  /// in chunks of `MAX_REGISTRATION_IDS`, concurrently, and the responses
+  /// are combined into one. See `GcmClient::send_multicast` for details.
+  /// Leave `to` empty, as for `registration_ids`.
+  pub fn send_multicast(self, tokens: Vec<&'a str>, api_key: &'a str) -> Result<GcmResponse, GcmError> {
+    GcmClient::new().send_multicast(&self, &tokens, api_key)
+  }
+
+  /// The JSON body `send` would POST for the message, e.g. for an audit
+  /// log. The message isn't validated, and the defaults of a `GcmClient`
+  /// aren't applied.
+  /// # Examples:
+  /// ```rust
+  /// use gcm::Message;
+  ///
+  /// let json = Message::new("<registration id>").dry_run(true).to_json().unwrap();
+  ///
+  /// assert_eq!(json, r#"{"to":"<registration id>","dry_run":true}"#);
+  /// ```
+  pub fn to_json(&self) -> Result<String, GcmError> {
+    Ok(to_string(self)?)
+  }
+
+  /// Check the message for mistakes GCM would reject it for, without
+  /// making a network call. `send` does this before sending. A message
+  /// must have exactly one of `to`, `registration_ids` or `condition`, with
+  /// 1 to `MAX_REGISTRATION_IDS` registration ids, and its payload (`data` and `notification`) must fit in
+  /// `MAX_PAYLOAD_SIZE`, or `MAX_TOPIC_PAYLOAD_SIZE` when sent to a topic.
+  /// `time_to_live` must be between 0 and `MAX_TIME_TO_LIVE`, and a
+  /// `collapse_key` no longer than `MAX_COLLAPSE_KEY_LENGTH`. A
+  /// `restricted_package_name` must be a valid Android package name, and
+  /// `data` keys can't be reserved words (`InvalidDataKey`). High
+  /// priority messages are delivered right away, so they can't also set
+  /// `delay_while_idle`, nor a `collapse_key` when they are
+  /// `content_available`. A notification color must be in #rgb or #rrggbb
+  /// format. Finally, the message must carry something: a `notification`,
+  /// `data`, `content_available`, or a `webpush`, `apns` or `android`
+  /// config.
+  pub fn validate(&self) -> Result<(), GcmError> {
+    let recipients = [
+      self.to.is_some(),
+      self.registration_ids.is_some(),
+      self.condition.is_some()
+    ];
+
+    match recipients.iter().filter(|&&is_set| is_set).count() {
+      0 => return Err(GcmError::InvalidMessage(
+        "one of `to`, `registration_ids` or `condition` must be set".to_string())),
+      1 => {},
+      _ => return Err(GcmError::InvalidMessage(
+        "only one of `to`, `registration_ids` or `condition` can be set".to_string()))
+    }
+
+    if let Some(ref ids) = self.registration_ids {
+      if ids.is_empty() || ids.len() > MAX_REGISTRATION_IDS {
+        return Err(GcmError::InvalidMessage(format!(
+          "`registration_ids` must have between 1 and {} ids, got {}", MAX_REGISTRATION_IDS, ids.len())));
+      }
+    }
+
+    if let Some(time_to_live) = self.time_to_live {
+      if time_to_live < 0 || time_to_live > MAX_TIME_TO_LIVE {
+        return Err(GcmError::InvalidMessage(format!(
+          "`time_to_live` must be between 0 and {} seconds, got {}", MAX_TIME_TO_LIVE, time_to_live)));
+      }
+    }
+
+    if let Some(collapse_key) = self.collapse_key {
+      if collapse_key.is_empty() || collapse_key.len() > MAX_COLLAPSE_KEY_LENGTH {
+        return Err(GcmError::InvalidMessage(format!(
+          "`collapse_key` must be between 1 and {} bytes, got {}", MAX_COLLAPSE_KEY_LENGTH, collapse_key.len())));
+      }
+    }
+
+    if let Some(ref data) = self.data {
+      if let Some(key) = data.keys().find(|key| is_reserved_data_key(key)) {
+        return Err(GcmError::InvalidDataKey(key.clone()));
+      }
+    }
+
+    if let Some(package_name) = self.restricted_package_name {
+      if !is_package_name(package_name) {
+        return Err(GcmError::InvalidMessage(format!(
+          "invalid `restricted_package_name` `{}`, expected a name like `com.example.app`", package_name)));
+      }
+    }
+
+    if let Some(ref fcm_options) = self.fcm_options {
+      if !is_analytics_label(&fcm_options.analytics_label) {
+        return Err(GcmError::InvalidMessage(format!(
+          "invalid `analytics_label` `{}`, expected 1 to {} of `[a-zA-Z0-9-_.~%]`",
+          fcm_options.analytics_label, MAX_ANALYTICS_LABEL_LENGTH)));
+      }
+    }
+
+    if self.priority == Some(Priority::High) && self.delay_while_idle == Some(true) {
+      return Err(GcmError::InvalidMessage(
+        "`delay_while_idle` can't be set on a high priority message".to_string()));
+    }
+
+    if self.collapse_key.is_some() && self.priority == Some(Priority::High) && self.content_available == Some(true) {
+      return Err(GcmError::InvalidMessage(
+        "`collapse_key` can't be set on a high priority `content_available` message, which isn't collapsible; \
+         use normal priority or drop the `collapse_key`".to_string()));
+    }
+
+    if let Some(color) = self.notification.as_ref().and_then(|notification| notification.color()) {
+      if !notification::is_valid_color(color) {
+        return Err(GcmError::InvalidMessage(format!("invalid color `{}`, expected #rgb or #rrggbb", color)));
       }
     }
+
+    let size = self.payload_size()?;
+    let limit = if self.is_topic() { MAX_TOPIC_PAYLOAD_SIZE } else { MAX_PAYLOAD_SIZE };
+
+    if size > limit {
+      return Err(GcmError::PayloadTooLarge { size: Some(size), limit: Some(limit) });
+    }
+
+    if self.has_no_payload() {
+      return Err(GcmError::InvalidMessage(
+        "one of `notification`, `data`, `content_available`, `webpush`, `apns` or `android` must be set".to_string()));
+    }
+
+    Ok(())
+  }
+
+  // Whether there is nothing to deliver, usually because assembling the
+  // payload went wrong.
+  fn has_no_payload(&self) -> bool {
+    self.notification.is_none() &&
+      is_empty_data(&self.data) &&
+      self.content_available != Some(true) &&
+      self.webpush.is_none() &&
+      self.apns.is_none() &&
+      self.android.is_none()
+  }
+
+  fn is_topic(&self) -> bool {
+    self.get_to().starts_with("/topics/") || self.condition.is_some()
+  }
+
+  // The serialized size of the parts of the message GCM limits.
+  fn payload_size(&self) -> Result<usize, GcmError> {
+    let mut size = 0;
+
+    if let Some(ref data) = self.data {
+      size += to_string(data)?.len();
+    }
+    if let Some(ref notification) = self.notification {
+      size += to_string(notification)?.len();
+    }
+
+    Ok(size)
   }
 
-  fn parse_response(status: StatusCode, body: &str) -> Result<GcmResponse, GcmError> {
+  pub(crate) fn parse_response(status: u16, body: &str, retry_after: Option<&str>) -> Result<GcmResponse, GcmError> {
   	//200 Ok: Request was successful!
-  	if status == StatusCode::Ok {
-      return from_str(body).or_else(|_| Err(GcmError::InvalidJsonBody));
-  	}
-  	//check for server error (5xx)
-  	if status.class() == StatusClass::ServerError {
-  		return Err(GcmError::ServerError);
-  	}
-  	//match remaining status codes
-  	match status {
-  		StatusCode::Unauthorized => Err(GcmError::Unauthorized),
-  		StatusCode::BadRequest => Err(GcmError::InvalidMessage(body.to_string())),
-  		_ => Err(GcmError::InvalidMessage("Unknown Error".to_string()))
+  	if status == 200 {
+      let mut response: GcmResponse = from_str(body)?;
+
+      // Topic and condition sends report a failure as a top-level `error`.
+      if let Some(error) = response.error.take() {
+        return Err(GcmError::MessageRejected(error));
+      }
+
+      response.raw = body.to_string();
+      return Ok(response);
   	}
+
+  	Err(status_error(status, body, retry_after))
   }
 }
+
+// The error for a response other than 200 OK. Shared with the Instance ID
+// API, which answers the same way.
+pub(crate) fn status_error(status: u16, body: &str, retry_after: Option<&str>) -> GcmError {
+	//check for server error (5xx)
+	if (500..600).contains(&status) {
+		return GcmError::ServerError {
+			status: status,
+			retry_after: retry_after.and_then(retry_after::parse)
+		};
+	}
+	//match remaining status codes
+	match status {
+		401 => GcmError::Unauthorized,
+		404 => GcmError::NotFound,
+		413 => GcmError::PayloadTooLarge { size: None, limit: None },
+		429 => GcmError::RateLimited {
+			retry_after: retry_after.and_then(retry_after::parse)
+		},
+		400 => match ErrorBody::parse(body) {
+			Some(error) => GcmError::InvalidRequest(error),
+			None => GcmError::BadRequest { status: 400, body: body.to_string(), request_body: None }
+		},
+		_ => GcmError::BadRequest { status: status, body: body.to_string(), request_body: None }
+	}
+}
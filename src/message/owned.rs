@@ -0,0 +1,120 @@
+use {Message, Priority, GcmResponse, GcmError};
+use client::GcmClient;
+use message::{priority_lowercase, is_empty_data, FcmOptions};
+use notification::Notification;
+use webpush::WebpushConfig;
+use apns::ApnsConfig;
+use android::AndroidConfig;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+/// A `Message` that owns its data, so that it can be stored and sent
+/// later, even from another process. It serializes to the same JSON as the
+/// `Message` it was created from, and can be deserialized from it.
+/// # Examples:
+/// ```no_run
+/// # extern crate gcm;
+/// # extern crate serde_json;
+/// # fn main() {
+/// use gcm::{Message, OwnedMessage};
+///
+/// let message = Message::new("<registration id>").content_available(true);
+/// let queued = serde_json::to_string(&OwnedMessage::from(message)).unwrap();
+///
+/// // ...later, in a worker:
+/// let message: OwnedMessage = serde_json::from_str(&queued).unwrap();
+/// let result = message.send("<GCM API Key>");
+/// # }
+/// ```
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OwnedMessage {
+  #[serde(default, skip_serializing_if = "String::is_empty")]
+  to: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  registration_ids: Option<Vec<String>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  condition: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  collapse_key: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none", serialize_with = "priority_lowercase")]
+  priority: Option<Priority>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  content_available: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  delay_while_idle: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  time_to_live: Option<i32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  restricted_package_name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  dry_run: Option<bool>,
+  #[serde(skip_serializing_if = "is_empty_data")]
+  data: Option<HashMap<String, Value>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  notification: Option<Notification<'static>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  webpush: Option<WebpushConfig>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  apns: Option<ApnsConfig>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  android: Option<AndroidConfig>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  fcm_options: Option<FcmOptions>,
+}
+
+impl OwnedMessage {
+  /// Send the message using your GCM API Key.
+  pub fn send(&self, api_key: &str) -> Result<GcmResponse, GcmError> {
+    GcmClient::new().send(&Message::from(self), api_key)
+  }
+}
+
+impl<'a> From<Message<'a>> for OwnedMessage {
+  fn from(message: Message<'a>) -> OwnedMessage {
+    OwnedMessage {
+      to: message.to.map(Cow::into_owned).unwrap_or_default(),
+      registration_ids: message.registration_ids,
+      condition: message.condition.map(|condition| condition.to_string()),
+      collapse_key: message.collapse_key.map(|collapse_key| collapse_key.to_string()),
+      priority: message.priority,
+      content_available: message.content_available,
+      delay_while_idle: message.delay_while_idle,
+      time_to_live: message.time_to_live,
+      restricted_package_name: message.restricted_package_name.map(|name| name.to_string()),
+      dry_run: message.dry_run,
+      data: message.data,
+      notification: message.notification.map(Notification::into_owned),
+      webpush: message.webpush,
+      apns: message.apns,
+      android: message.android,
+      fcm_options: message.fcm_options,
+    }
+  }
+}
+
+/// Borrow an `OwnedMessage` as a `Message`, e.g. to send it with a
+/// `GcmClient`.
+impl<'a> From<&'a OwnedMessage> for Message<'a> {
+  fn from(message: &'a OwnedMessage) -> Message<'a> {
+    Message {
+      to: if message.to.is_empty() { None } else { Some(Cow::Borrowed(&message.to)) },
+      registration_ids: message.registration_ids.clone(),
+      condition: message.condition.as_ref().map(|condition| &condition[..]),
+      collapse_key: message.collapse_key.as_ref().map(|collapse_key| &collapse_key[..]),
+      priority: message.priority,
+      content_available: message.content_available,
+      delay_while_idle: message.delay_while_idle,
+      time_to_live: message.time_to_live,
+      restricted_package_name: message.restricted_package_name.as_ref().map(|name| &name[..]),
+      dry_run: message.dry_run,
+      data: message.data.clone(),
+      notification: message.notification.clone(),
+      webpush: message.webpush.clone(),
+      apns: message.apns.clone(),
+      android: message.android.clone(),
+      fcm_options: message.fcm_options.clone(),
+    }
+  }
+}
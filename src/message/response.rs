@@ -1,61 +1,529 @@
+use std::collections::HashMap;
 use std::fmt::{self, Display};
 use std::error;
+use std::time::Duration;
 
 use serde::{Deserialize, Deserializer};
+use serde::de::Error as DeError;
+use serde_json::{self, Value};
+use transport::TransportError;
 
 #[derive(Deserialize, Debug)]
 pub struct GcmResponse {
-  pub message_id: Option<u64>,
-  pub error: Option<String>,
-  pub multicast_id: Option<i64>,
+  #[serde(deserialize_with = "deserialize_message_id", default)]
+  pub message_id: Option<String>,
+  /// The failure of a topic or condition send. `send` turns it into a
+  /// `MessageRejected` error, so it is `None` in the responses it returns.
+  pub error: Option<MessageResultError>,
+  /// The id GCM gave a multicast send, a non-negative 64-bit number.
+  pub multicast_id: Option<u64>,
   pub success: Option<u64>,
   pub failure: Option<u64>,
   pub canonical_ids: Option<u64>,
-  pub results: Option<Vec<MessageResult>>
+  pub results: Option<Vec<MessageResult>>,
+  /// The body of the response, as GCM sent it. Useful for logging, or for
+  /// fields this crate doesn't know about. For a multicast send split into
+  /// several requests, a JSON array of the bodies of each request.
+  #[serde(skip)]
+  pub raw: String
+}
+
+impl GcmResponse {
+  /// Whether every message was delivered, i.e. the `failure` count is 0
+  /// and there is no top-level `error`. A topic send only has a
+  /// `message_id` when it succeeds.
+  pub fn is_success(&self) -> bool {
+    self.failure.unwrap_or(0) == 0 && self.error.is_none()
+  }
+
+  /// The positions in `results` of the messages that failed.
+  pub fn failed_indices(&self) -> Vec<usize> {
+    self.results.iter()
+      .flat_map(|results| results.iter().enumerate())
+      .filter(|&(_, result)| result.error.is_some())
+      .map(|(index, _)| index)
+      .collect()
+  }
+
+  /// The results of the messages that failed.
+  pub fn failed_results<'a>(&'a self) -> impl Iterator<Item = &'a MessageResult> + 'a {
+    self.results.iter()
+      .flat_map(|results| results.iter())
+      .filter(|result| result.error.is_some())
+  }
+
+  /// Given the registration ids the message was sent to, in the order they
+  /// were sent, returns the ones GCM reported as `NotRegistered` or
+  /// `InvalidRegistration`. These should be removed from your database.
+  ///
+  /// Results are matched to ids by position; if the lengths differ, the
+  /// extra ids or results are ignored.
+  pub fn unregistered_tokens(&self, sent_ids: &[String]) -> Vec<String> {
+    let results = match self.results {
+      Some(ref results) => results,
+      None => return Vec::new()
+    };
+
+    sent_ids.iter()
+      .zip(results.iter())
+      .filter(|&(_, result)| match result.error {
+        Some(MessageResultError::NotRegistered) | Some(MessageResultError::InvalidRegistration) => true,
+        _ => false
+      })
+      .map(|(id, _)| id.clone())
+      .collect()
+  }
+
+  /// Given the registration ids the message was sent to, in the order they
+  /// were sent, returns the ones whose error is worth retrying later, per
+  /// `MessageResultError::is_retriable`. Permanent errors, such as
+  /// `NotRegistered` or `MismatchSenderId`, are left out.
+  ///
+  /// Results are matched to ids by position, as in `unregistered_tokens`.
+  pub fn retriable_tokens(&self, sent_ids: &[String]) -> Vec<String> {
+    let results = match self.results {
+      Some(ref results) => results,
+      None => return Vec::new()
+    };
+
+    sent_ids.iter()
+      .zip(results.iter())
+      .filter(|&(_, result)| result.error.as_ref().map_or(false, MessageResultError::is_retriable))
+      .map(|(id, _)| id.clone())
+      .collect()
+  }
+
+  /// Given the registration ids the message was sent to, in the order they
+  /// were sent, returns the result for each id. Returns
+  /// `ResultCountMismatch` if there isn't exactly one result per id.
+  pub fn results_by_token(&self, sent_ids: &[String]) -> Result<HashMap<String, &MessageResult>, GcmError> {
+    let results = self.results.as_ref().map(|results| &results[..]).unwrap_or(&[]);
+
+    if results.len() != sent_ids.len() {
+      return Err(GcmError::ResultCountMismatch { sent: sent_ids.len(), results: results.len() });
+    }
+
+    Ok(sent_ids.iter().cloned().zip(results.iter()).collect())
+  }
+
+  /// Given the registration ids the message was sent to, in the order they
+  /// were sent, returns `(old, new)` pairs for the ids GCM reported a
+  /// canonical registration id for. Replace the old ids with the new ones
+  /// in your database.
+  ///
+  /// Results are matched to ids by position, as in `unregistered_tokens`.
+  pub fn canonical_updates(&self, sent_ids: &[String]) -> Vec<(String, String)> {
+    let results = match self.results {
+      Some(ref results) => results,
+      None => return Vec::new()
+    };
+
+    sent_ids.iter()
+      .zip(results.iter())
+      .filter_map(|(id, result)| result.registration_id.as_ref()
+        .map(|canonical_id| (id.clone(), canonical_id.clone())))
+      .collect()
+  }
+}
+
+/// The counts of a `GcmResponse`, with absent counts as 0.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct ResponseSummary {
+  pub success: u64,
+  pub failure: u64,
+  pub canonical_ids: u64,
+}
+
+impl GcmResponse {
+  /// The success, failure and canonical id counts.
+  pub fn summary(&self) -> ResponseSummary {
+    ResponseSummary {
+      success: self.success.unwrap_or(0),
+      failure: self.failure.unwrap_or(0),
+      canonical_ids: self.canonical_ids.unwrap_or(0),
+    }
+  }
+}
+
+// Prints the fields that are present, e.g.
+// `multicast_id=7 success=1 failure=1 canonical_ids=0`.
+impl Display for GcmResponse {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut fields = Vec::new();
+
+    if let Some(ref message_id) = self.message_id {
+      fields.push(format!("message_id={}", message_id));
+    }
+    if let Some(ref error) = self.error {
+      fields.push(format!("error={}", error));
+    }
+    if let Some(multicast_id) = self.multicast_id {
+      fields.push(format!("multicast_id={}", multicast_id));
+    }
+    if let Some(success) = self.success {
+      fields.push(format!("success={}", success));
+    }
+    if let Some(failure) = self.failure {
+      fields.push(format!("failure={}", failure));
+    }
+    if let Some(canonical_ids) = self.canonical_ids {
+      fields.push(format!("canonical_ids={}", canonical_ids));
+    }
+
+    write!(f, "{}", fields.join(" "))
+  }
 }
 
 #[derive(Deserialize, Debug)]
 pub struct MessageResult {
   #[serde(deserialize_with = "deserialize_message_id", default)]
-  pub message_id: Option<u64>,
-  pub registration_id: Option<u64>,
-  pub error: Option<String>
+  pub message_id: Option<String>,
+  pub registration_id: Option<String>,
+  pub error: Option<MessageResultError>
 }
 
-fn deserialize_message_id<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+// Message ids are strings like `0:1294000...`, except for topic sends,
+// where GCM returns a plain number. Accept both, keeping the original text.
+fn deserialize_message_id<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
     where D: Deserializer<'de> {
-  match u64::deserialize(deserializer) {
-    Ok(val) => Ok(Some(val)),
-    Err(_) => Ok(None)
+  match Option::<Value>::deserialize(deserializer)? {
+    Some(Value::String(id)) => Ok(Some(id)),
+    Some(Value::Number(id)) => Ok(Some(id.to_string())),
+    Some(Value::Null) | None => Ok(None),
+    Some(other) => Err(D::Error::custom(format!("invalid message_id: {}", other)))
+  }
+}
+
+/// The error codes GCM reports for a message, as documented in
+/// https://developers.google.com/cloud-messaging/http-server-ref#error-codes.
+/// Codes this crate doesn't know about end up in `Other`.
+#[derive(PartialEq, Debug, Clone)]
+pub enum MessageResultError {
+  MissingRegistration,
+  InvalidRegistration,
+  NotRegistered,
+  MismatchSenderId,
+  MessageTooBig,
+  InvalidDataKey,
+  InvalidTtl,
+  InvalidPackageName,
+  Unavailable,
+  InternalServerError,
+  DeviceMessageRateExceeded,
+  TopicsMessageRateExceeded,
+  Other(String)
+}
+
+impl MessageResultError {
+  /// The error code, as GCM spells it.
+  pub fn as_str(&self) -> &str {
+    match *self {
+      MessageResultError::MissingRegistration => "MissingRegistration",
+      MessageResultError::InvalidRegistration => "InvalidRegistration",
+      MessageResultError::NotRegistered => "NotRegistered",
+      MessageResultError::MismatchSenderId => "MismatchSenderId",
+      MessageResultError::MessageTooBig => "MessageTooBig",
+      MessageResultError::InvalidDataKey => "InvalidDataKey",
+      MessageResultError::InvalidTtl => "InvalidTtl",
+      MessageResultError::InvalidPackageName => "InvalidPackageName",
+      MessageResultError::Unavailable => "Unavailable",
+      MessageResultError::InternalServerError => "InternalServerError",
+      MessageResultError::DeviceMessageRateExceeded => "DeviceMessageRateExceeded",
+      MessageResultError::TopicsMessageRateExceeded => "TopicsMessageRateExceeded",
+      MessageResultError::Other(ref code) => code
+    }
+  }
+
+  /// Whether GCM documents the error as temporary, so that sending the
+  /// message again, with exponential backoff, may succeed: `Unavailable`,
+  /// `InternalServerError` and `DeviceMessageRateExceeded`.
+  pub fn is_retriable(&self) -> bool {
+    match *self {
+      MessageResultError::Unavailable
+      | MessageResultError::InternalServerError
+      | MessageResultError::DeviceMessageRateExceeded => true,
+      _ => false
+    }
+  }
+}
+
+impl<'a> From<&'a str> for MessageResultError {
+  fn from(code: &'a str) -> MessageResultError {
+    match code {
+      "MissingRegistration" => MessageResultError::MissingRegistration,
+      "InvalidRegistration" => MessageResultError::InvalidRegistration,
+      "NotRegistered" => MessageResultError::NotRegistered,
+      "MismatchSenderId" => MessageResultError::MismatchSenderId,
+      "MessageTooBig" => MessageResultError::MessageTooBig,
+      "InvalidDataKey" => MessageResultError::InvalidDataKey,
+      "InvalidTtl" => MessageResultError::InvalidTtl,
+      "InvalidPackageName" => MessageResultError::InvalidPackageName,
+      "Unavailable" => MessageResultError::Unavailable,
+      "InternalServerError" => MessageResultError::InternalServerError,
+      "DeviceMessageRateExceeded" => MessageResultError::DeviceMessageRateExceeded,
+      "TopicsMessageRateExceeded" => MessageResultError::TopicsMessageRateExceeded,
+      other => MessageResultError::Other(other.to_string())
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for MessageResultError {
+  fn deserialize<D>(deserializer: D) -> Result<MessageResultError, D::Error>
+      where D: Deserializer<'de> {
+    let code = String::deserialize(deserializer)?;
+    Ok(MessageResultError::from(&code[..]))
+  }
+}
+
+impl Display for MessageResultError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+/// The structured error in the body of a rejected request, such as
+/// `{"error": "InvalidParameters", "error_description": "..."}`, or the
+/// `{"error": {"status": "...", "message": "..."}}` form FCM uses.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ErrorBody {
+  pub code: String,
+  pub description: Option<String>,
+  /// The `code`, if it is one of the GCM error codes this crate knows,
+  /// like `InvalidRegistration` or `MessageTooBig`.
+  pub reason: Option<MessageResultError>,
+  /// The body of the rejected request, kept with the `debug_bodies`
+  /// feature. See `GcmError::request_body`.
+  pub request_body: Option<String>
+}
+
+impl ErrorBody {
+  /// Parse an error body. Returns `None` if it has no `error` field, or
+  /// isn't JSON and isn't a known GCM error code by itself either.
+  pub fn parse(body: &str) -> Option<ErrorBody> {
+    let json: Value = match ::serde_json::from_str(body) {
+      Ok(json) => json,
+      Err(_) => return ErrorBody::parse_code(body.trim())
+    };
+    let text = |value: &Value| value.as_str().map(|text| text.to_string());
+
+    let (code, description) = match json.get("error") {
+      Some(&Value::String(ref code)) => (code.clone(), json.get("error_description")
+        .or_else(|| json.get("description"))
+        .or_else(|| json.get("message"))
+        .and_then(text)),
+      Some(&Value::Object(ref error)) => (error.get("status")
+        .and_then(text)
+        .or_else(|| error.get("code").map(|code| code.to_string()))?,
+        error.get("message").and_then(text)),
+      _ => return None
+    };
+
+    Some(ErrorBody {
+      reason: known_reason(&code),
+      code: code,
+      description: description,
+      request_body: None
+    })
+  }
+
+  // A plain text body, like `InvalidRegistration` or
+  // `Error=InvalidRegistration`.
+  fn parse_code(body: &str) -> Option<ErrorBody> {
+    let code = body.trim_start_matches("Error=");
+
+    known_reason(code).map(|reason| ErrorBody {
+      code: code.to_string(),
+      description: None,
+      reason: Some(reason),
+      request_body: None
+    })
+  }
+}
+
+fn known_reason(code: &str) -> Option<MessageResultError> {
+  match MessageResultError::from(code) {
+    MessageResultError::Other(_) => None,
+    reason => Some(reason)
+  }
+}
+
+impl Display for ErrorBody {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.description {
+      Some(ref description) => write!(f, "{}: {}", self.code, description),
+      None => write!(f, "{}", self.code)
+    }
+  }
+}
+
+/// A JSON error, kept in `GcmError::InvalidJsonBody`.
+#[derive(Debug)]
+pub struct JsonError(pub serde_json::Error);
+
+impl Display for JsonError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+// serde_json errors can't be compared, so compare their messages.
+impl PartialEq for JsonError {
+  fn eq(&self, other: &JsonError) -> bool {
+    self.to_string() == other.to_string()
   }
 }
 
 #[derive(PartialEq, Debug)]
 pub enum GcmError {
   Unauthorized,
+  /// The endpoint answered 404, which usually means its URL is wrong.
+  NotFound,
+  MissingApiKey(String),
   InvalidMessage(String),
-  ServerError,
-  InvalidJsonBody
+  InvalidDataKey(String),
+  InvalidRequest(ErrorBody),
+  /// GCM accepted the request, but not the message, e.g. a topic send
+  /// over the rate limit. The response has a top-level `error` in place of
+  /// a `message_id`.
+  MessageRejected(MessageResultError),
+  /// GCM rejected the request with a status other than those above. With
+  /// the `debug_bodies` feature, `request_body` is the body that was sent.
+  BadRequest { status: u16, body: String, request_body: Option<String> },
+  ServerError { status: u16, retry_after: Option<Duration> },
+  RateLimited { retry_after: Option<Duration> },
+  /// A message couldn't be serialized, or a response parsed. Holds the
+  /// serde_json error, or `None` if the JSON was valid but of the wrong
+  /// shape.
+  InvalidJsonBody(Option<JsonError>),
+  /// The response body couldn't be parsed, and wasn't valid UTF-8 or was
+  /// cut short. `body` has the bytes that were received.
+  InvalidResponseBody { status: u16, body: Vec<u8> },
+  InvalidEndpoint(String),
+  HttpError(TransportError),
+  TlsInitError(TransportError),
+  /// The payload is over the limit. `size` and `limit` are known when
+  /// `validate` caught it, and `None` when GCM answered 413.
+  PayloadTooLarge { size: Option<usize>, limit: Option<usize> },
+  ResultCountMismatch { sent: usize, results: usize }
+}
+
+impl GcmError {
+  /// How long GCM asked us to wait before retrying, taken from the
+  /// `Retry-After` header of the response. `None` if the header was absent
+  /// or the error isn't retriable.
+  pub fn retry_after(&self) -> Option<Duration> {
+    match *self {
+      GcmError::ServerError { retry_after, .. } => retry_after,
+      GcmError::RateLimited { retry_after } => retry_after,
+      _ => None
+    }
+  }
+
+  /// Whether sending the request again may succeed: server errors, rate
+  /// limiting, and failures to reach the server other than TLS errors.
+  pub fn is_retriable(&self) -> bool {
+    match *self {
+      GcmError::ServerError { .. } | GcmError::RateLimited { .. } | GcmError::HttpError(_) => true,
+      _ => false
+    }
+  }
+
+  /// The body of the request GCM rejected, for an `InvalidRequest` or a
+  /// `BadRequest`. Only kept with the `debug_bodies` feature, to not hold
+  /// on to every payload in production; `None` otherwise. The API key is
+  /// sent in a header, so it is never part of it.
+  pub fn request_body(&self) -> Option<&str> {
+    match *self {
+      GcmError::InvalidRequest(ref error) => error.request_body.as_ref().map(|body| &body[..]),
+      GcmError::BadRequest { ref request_body, .. } => request_body.as_ref().map(|body| &body[..]),
+      _ => None
+    }
+  }
+
+  // Attach the body of the request to the errors that can carry it.
+  #[cfg(feature = "debug_bodies")]
+  pub(crate) fn with_request_body(self, request_body: &str) -> GcmError {
+    match self {
+      GcmError::InvalidRequest(mut error) => {
+        error.request_body = Some(request_body.to_string());
+        GcmError::InvalidRequest(error)
+      },
+      GcmError::BadRequest { status, body, .. } =>
+        GcmError::BadRequest { status: status, body: body, request_body: Some(request_body.to_string()) },
+      error => error
+    }
+  }
+
+  /// The HTTP status code of the response that caused the error. `None`
+  /// for errors raised before a response was received.
+  pub fn status(&self) -> Option<u16> {
+    match *self {
+      GcmError::Unauthorized => Some(401),
+      GcmError::NotFound => Some(404),
+      GcmError::PayloadTooLarge { size: None, .. } => Some(413),
+      GcmError::InvalidRequest(_) => Some(400),
+      GcmError::BadRequest { status, .. } => Some(status),
+      GcmError::InvalidResponseBody { status, .. } => Some(status),
+      GcmError::ServerError { status, .. } => Some(status),
+      GcmError::RateLimited { .. } => Some(429),
+      _ => None
+    }
+  }
 }
 
 impl Display for GcmError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match *self {
       GcmError::Unauthorized => write!(f, "UnauthorizedError"),
-      GcmError::ServerError => write!(f, "ServerError"),
+      GcmError::NotFound => write!(f, "NotFound: check the endpoint URL"),
+      GcmError::MissingApiKey(ref var) => write!(f, "MissingApiKey: {} is not set", var),
+      GcmError::ServerError { status, .. } => write!(f, "ServerError: {}", status),
+      GcmError::RateLimited { .. } => write!(f, "RateLimited"),
       GcmError::InvalidMessage(ref message) => write!(f, "InvalidMessage: {}", message),
-      GcmError::InvalidJsonBody => write!(f, "InvalidJsonBody")
+      GcmError::InvalidDataKey(ref key) => write!(f, "InvalidDataKey: `{}` is reserved", key),
+      GcmError::InvalidRequest(ref error) => write!(f, "InvalidRequest: {}", error),
+      GcmError::MessageRejected(ref error) => write!(f, "MessageRejected: {}", error),
+      GcmError::BadRequest { status, ref body, .. } => write!(f, "BadRequest: {} {}", status, body),
+      GcmError::InvalidJsonBody(Some(ref error)) => write!(f, "InvalidJsonBody: {}", error),
+      GcmError::InvalidJsonBody(None) => write!(f, "InvalidJsonBody"),
+      GcmError::InvalidResponseBody { status, ref body } =>
+        write!(f, "InvalidResponseBody: {} bytes with status {}", body.len(), status),
+      GcmError::InvalidEndpoint(ref endpoint) => write!(f, "InvalidEndpoint: {}", endpoint),
+      GcmError::HttpError(ref error) => write!(f, "HttpError: {}", error),
+      GcmError::TlsInitError(ref error) => write!(f, "TlsInitError: {}", error),
+      GcmError::PayloadTooLarge { size: Some(size), limit: Some(limit) } =>
+        write!(f, "PayloadTooLarge: {} bytes, the limit is {}", size, limit),
+      GcmError::PayloadTooLarge { .. } => write!(f, "PayloadTooLarge"),
+      GcmError::ResultCountMismatch { sent, results } =>
+        write!(f, "ResultCountMismatch: sent {} ids, got {} results", sent, results)
     }
   }
 }
 
 impl error::Error for GcmError {
-  fn description(&self) -> &str {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
     match *self {
-      GcmError::Unauthorized => "UnauthorizedError",
-      GcmError::ServerError => "ServerError",
-      GcmError::InvalidMessage(_) => "InvalidMessage",
-      GcmError::InvalidJsonBody => "InvalidJsonBody"
+      GcmError::HttpError(ref error) => Some(error),
+      GcmError::TlsInitError(ref error) => Some(error),
+      GcmError::InvalidJsonBody(Some(ref error)) => Some(&error.0),
+      _ => None
+    }
+  }
+}
+
+// Serializing a message, or parsing a response, failed.
+impl From<serde_json::Error> for GcmError {
+  fn from(error: serde_json::Error) -> GcmError {
+    GcmError::InvalidJsonBody(Some(JsonError(error)))
+  }
+}
+
+impl From<TransportError> for GcmError {
+  fn from(error: TransportError) -> GcmError {
+    if error.is_tls() {
+      GcmError::TlsInitError(error)
+    } else {
+      GcmError::HttpError(error)
     }
   }
 }
@@ -0,0 +1,72 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// The last year an HTTP-date can have; later ones are taken as malformed.
+const MAX_YEAR: u64 = 9999;
+
+/// Parses the value of a `Retry-After` header into the time to wait. GCM
+/// sends either a number of seconds or an RFC 1123 HTTP-date.
+pub fn parse(value: &str) -> Option<Duration> {
+  parse_at(value, SystemTime::now())
+}
+
+/// Same as `parse`, but HTTP-dates are measured from `now`. Dates in the
+/// past yield a zero duration.
+pub fn parse_at(value: &str, now: SystemTime) -> Option<Duration> {
+  let value = value.trim();
+
+  if let Ok(seconds) = value.parse::<u64>() {
+    return Some(Duration::from_secs(seconds));
+  }
+
+  parse_http_date(value)
+    .map(|date| date.duration_since(now).unwrap_or(Duration::from_secs(0)))
+}
+
+// Parses dates of the form `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn parse_http_date(value: &str) -> Option<SystemTime> {
+  let parts: Vec<&str> = value.split_whitespace().collect();
+
+  if parts.len() != 6 || !parts[0].ends_with(',') || parts[5] != "GMT" {
+    return None;
+  }
+
+  let day: u64 = parts[1].parse().ok()?;
+  let month = match parts[2] {
+    "Jan" => 1, "Feb" => 2, "Mar" => 3, "Apr" => 4, "May" => 5, "Jun" => 6,
+    "Jul" => 7, "Aug" => 8, "Sep" => 9, "Oct" => 10, "Nov" => 11, "Dec" => 12,
+    _ => return None
+  };
+  let year: u64 = parts[3].parse().ok()?;
+
+  let time: Vec<&str> = parts[4].split(':').collect();
+  if time.len() != 3 {
+    return None;
+  }
+  let hours: u64 = time[0].parse().ok()?;
+  let minutes: u64 = time[1].parse().ok()?;
+  let seconds: u64 = time[2].parse().ok()?;
+
+  if year < 1970 || year > MAX_YEAR || day < 1 || day > 31 || hours > 23 || minutes > 59 || seconds > 60 {
+    return None;
+  }
+
+  let seconds = days_since_epoch(year, month, day)?
+    .checked_mul(86400)?
+    .checked_add(hours * 3600 + minutes * 60 + seconds)?;
+  UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+// Days from 1970-01-01 to the given civil date, or `None` if there are
+// too many to count.
+fn days_since_epoch(year: u64, month: u64, day: u64) -> Option<u64> {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year / 400;
+  let year_of_era = year - era * 400;
+  let month_index = if month > 2 { month - 3 } else { month + 9 };
+  let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+  era.checked_mul(146097)?
+    .checked_add(day_of_era)?
+    .checked_sub(719468)
+}
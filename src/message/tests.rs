@@ -1,16 +1,33 @@
-use {Message, Priority};
-use GcmError;
+use {Message, OwnedMessage, Priority, GcmClient, GcmResponse, MockTransport, WebpushConfig, ApnsConfig, AndroidConfig};
+use {GcmError, TransportError, ErrorBody, MessageResultError, ResponseSummary, MAX_COLLAPSE_KEY_LENGTH, MAX_ANALYTICS_LABEL_LENGTH};
+use MAX_REGISTRATION_IDS;
 use notification::NotificationBuilder;
 
 use std::collections::HashMap;
-use hyper::status::StatusCode;
 use serde_json;
+use message::retry_after;
+use message::v1;
+use std::time::{Duration, UNIX_EPOCH};
+use std::error::Error;
 
 #[test]
 fn should_create_new_message() {
   let msg = Message::new("token");
 
-  assert_eq!(msg.to, "token");
+  assert_eq!(msg.to, Some("token".into()));
+}
+
+#[test]
+fn should_leave_to_unset_when_empty() {
+  assert_eq!(Message::new("").to, None);
+}
+
+#[test]
+fn should_create_multicast_message_without_to() {
+  let msg = Message::multicast(vec!["id1", "id2"]);
+
+  assert_eq!(msg.clone().content_available(true).validate(), Ok(()));
+  assert_eq!(serde_json::to_string(&msg).unwrap(), r#"{"registration_ids":["id1","id2"]}"#);
 }
 
 #[test]
@@ -25,6 +42,137 @@ fn should_set_registration_ids() {
   assert_eq!(msg.registration_ids, Some(vec!["id1".to_string()]));
 }
 
+#[test]
+fn should_read_back_message_fields() {
+  let msg = Message::new("token");
+
+  assert_eq!(msg.get_to(), "token");
+  assert_eq!(msg.get_registration_ids(), None);
+  assert_eq!(msg.get_priority(), None);
+  assert_eq!(msg.get_data(), None);
+  assert!(msg.get_notification().is_none());
+
+  let mut data = HashMap::new();
+  data.insert("my", "data");
+
+  let msg = Message::new("")
+      .registration_ids(vec!["id1"])
+      .condition("'a' in topics")
+      .collapse_key("key")
+      .priority(Priority::High)
+      .content_available(true)
+      .delay_while_idle(false)
+      .time_to_live(10)
+      .restricted_package_name("com.example")
+      .dry_run(true)
+      .data(data)
+      .notification(NotificationBuilder::new("title").finalize());
+
+  assert_eq!(msg.get_to(), "");
+  assert_eq!(msg.get_registration_ids(), Some(&["id1".to_string()][..]));
+  assert_eq!(msg.get_condition(), Some("'a' in topics"));
+  assert_eq!(msg.get_collapse_key(), Some("key"));
+  assert_eq!(msg.get_priority(), Some(Priority::High));
+  assert_eq!(msg.get_content_available(), Some(true));
+  assert_eq!(msg.get_delay_while_idle(), Some(false));
+  assert_eq!(msg.get_time_to_live(), Some(10));
+  assert_eq!(msg.get_restricted_package_name(), Some("com.example"));
+  assert_eq!(msg.get_dry_run(), Some(true));
+  assert_eq!(msg.get_data().unwrap().get("my"), Some(&json!("data")));
+  assert_eq!(msg.get_notification(), Some(&NotificationBuilder::new("title").finalize()));
+}
+
+#[test]
+fn should_validate_single_recipient() {
+  assert_eq!(Message::new("token").content_available(true).validate(), Ok(()));
+  assert_eq!(Message::new("").registration_ids(vec!["id1", "id2"]).content_available(true).validate(), Ok(()));
+  assert_eq!(Message::new("").condition("'a' in topics").content_available(true).validate(), Ok(()));
+}
+
+#[test]
+fn should_reject_both_to_and_registration_ids() {
+  let msg = Message::new("token")
+      .registration_ids(vec!["id1"]);
+
+  assert_eq!(msg.validate(), Err(GcmError::InvalidMessage(
+    "only one of `to`, `registration_ids` or `condition` can be set".to_string())));
+}
+
+#[test]
+fn should_reject_both_to_and_condition() {
+  let msg = Message::new("token")
+      .condition("'a' in topics");
+
+  assert_eq!(msg.validate(), Err(GcmError::InvalidMessage(
+    "only one of `to`, `registration_ids` or `condition` can be set".to_string())));
+}
+
+#[test]
+fn should_reject_missing_recipient() {
+  let msg = Message::new("");
+
+  assert_eq!(msg.validate(), Err(GcmError::InvalidMessage(
+    "one of `to`, `registration_ids` or `condition` must be set".to_string())));
+}
+
+#[test]
+fn should_validate_number_of_registration_ids() {
+  let ids = |count: usize| (0..count).map(|_| "id").collect::<Vec<_>>();
+  let error = |count: usize| Err(GcmError::InvalidMessage(format!(
+    "`registration_ids` must have between 1 and 1000 ids, got {}", count)));
+
+  assert_eq!(Message::new("").registration_ids(ids(0)).validate(), error(0));
+  assert_eq!(Message::new("").registration_ids(ids(1)).content_available(true).validate(), Ok(()));
+  assert_eq!(Message::new("").registration_ids(ids(MAX_REGISTRATION_IDS)).content_available(true).validate(), Ok(()));
+  assert_eq!(Message::new("").registration_ids(ids(MAX_REGISTRATION_IDS + 1)).validate(), error(1001));
+}
+
+#[test]
+fn should_set_condition() {
+  let msg = Message::new("");
+
+  assert_eq!(msg.condition, None);
+
+  let msg = Message::new("")
+      .condition("'a' in topics");
+
+  let json_result = serde_json::to_string(&msg);
+
+  assert_eq!(msg.condition, Some("'a' in topics"));
+  assert_eq!(msg.clone().content_available(true).validate(), Ok(()));
+  assert_eq!(json_result.unwrap(), r#"{"condition":"'a' in topics"}"#);
+}
+
+#[test]
+fn should_reject_payloads_over_the_limit() {
+  let value = "x".repeat(4100);
+  let mut data = HashMap::new();
+  data.insert("a", &value[..]);
+
+  let msg = Message::new("token").data(data);
+
+  assert_eq!(msg.validate(), Err(GcmError::PayloadTooLarge { size: Some(4108), limit: Some(4096) }));
+}
+
+#[test]
+fn should_use_the_smaller_limit_for_topics() {
+  let value = "x".repeat(3000);
+  let mut data = HashMap::new();
+  data.insert("a", &value[..]);
+
+  let msg = Message::new("token").data(data.clone());
+
+  assert_eq!(msg.validate(), Ok(()));
+
+  let msg = Message::new("/topics/news").data(data.clone());
+
+  assert_eq!(msg.validate(), Err(GcmError::PayloadTooLarge { size: Some(3008), limit: Some(2048) }));
+
+  let msg = Message::new("").condition("'news' in topics").data(data);
+
+  assert_eq!(msg.validate(), Err(GcmError::PayloadTooLarge { size: Some(3008), limit: Some(2048) }));
+}
+
 #[test]
 fn should_set_collapse_key() {
   let msg = Message::new("token");
@@ -53,6 +201,31 @@ fn should_set_priority() {
   assert_eq!(json_result.unwrap(), r#"{"to":"token","priority":"normal"}"#);
 }
 
+#[test]
+fn should_reuse_priority_across_messages() {
+  let priority = Priority::High;
+
+  let first = Message::new("token1").priority(priority);
+  let second = Message::new("token2").priority(priority);
+
+  assert_eq!(first.priority, Some(Priority::High));
+  assert_eq!(second.priority, Some(priority));
+  assert_eq!(serde_json::to_string(&second).unwrap(), r#"{"to":"token2","priority":"high"}"#);
+}
+
+#[test]
+fn should_deserialize_priority() {
+  let msg = Message::new("token").priority(Priority::High);
+  let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&msg).unwrap()).unwrap();
+
+  let priority: Priority = serde_json::from_value(json["priority"].clone()).unwrap();
+
+  assert_eq!(priority, Priority::High);
+  assert_eq!(serde_json::from_str::<Priority>(r#""NORMAL""#).unwrap(), Priority::Normal);
+  assert_eq!(serde_json::from_str::<Priority>(r#""High""#).unwrap(), Priority::High);
+  assert!(serde_json::from_str::<Priority>(r#""urgent""#).is_err());
+}
+
 #[test]
 fn should_set_content_available() {
   let msg = Message::new("token");
@@ -89,6 +262,16 @@ fn should_set_time_to_live() {
   assert_eq!(msg.time_to_live, Some(10));
 }
 
+#[test]
+fn should_validate_time_to_live_range() {
+  assert_eq!(Message::new("token").content_available(true).time_to_live(0).validate(), Ok(()));
+  assert_eq!(Message::new("token").content_available(true).time_to_live(2419200).validate(), Ok(()));
+  assert_eq!(Message::new("token").time_to_live(2419201).validate(), Err(GcmError::InvalidMessage(
+    "`time_to_live` must be between 0 and 2419200 seconds, got 2419201".to_string())));
+  assert_eq!(Message::new("token").time_to_live(-1).validate(), Err(GcmError::InvalidMessage(
+    "`time_to_live` must be between 0 and 2419200 seconds, got -1".to_string())));
+}
+
 #[test]
 fn should_set_restricted_package_name() {
   let msg = Message::new("token");
@@ -125,7 +308,30 @@ fn should_set_data() {
   let msg = Message::new("token")
       .data(data);
 
-  assert_eq!(msg.data.unwrap().get("my"), Some(&"data".to_string()));
+  assert_eq!(msg.data.unwrap().get("my"), Some(&json!("data")));
+}
+
+#[test]
+fn should_set_json_data() {
+  let mut data = HashMap::new();
+  data.insert("count".to_string(), json!(3));
+  data.insert("match".to_string(), json!({
+    "teams": ["India", "Australia"],
+    "live": true
+  }));
+
+  let msg = Message::new("token")
+      .data_json(data);
+
+  let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&msg).unwrap()).unwrap();
+
+  assert_eq!(json["data"], json!({
+    "count": 3,
+    "match": {
+      "teams": ["India", "Australia"],
+      "live": true
+    }
+  }));
 }
 
 #[test]
@@ -144,27 +350,194 @@ fn should_set_notifications() {
 
 #[test]
 fn should_parse_error_as_unauthorized() {
-  let result = Message::parse_response(StatusCode::Unauthorized, "Unauthorized");
+  let result = Message::parse_response(401, "Unauthorized", None);
 
   assert!(result.is_err());
   assert_eq!(result.err().unwrap(), GcmError::Unauthorized);
 }
 
 #[test]
-fn should_parse_error_as_invalid_message() {
-  let result = Message::parse_response(StatusCode::BadRequest, "INVALID_REGISTRATION");
+fn should_parse_error_as_bad_request() {
+  let result = Message::parse_response(400, "INVALID_REGISTRATION", None);
 
   assert!(result.is_err());
   assert_eq!(result.err().unwrap(), 
-    GcmError::InvalidMessage("INVALID_REGISTRATION".to_string()));
+    GcmError::BadRequest { status: 400, body: "INVALID_REGISTRATION".to_string(), request_body: None });
+}
+
+#[test]
+fn should_parse_error_as_not_found() {
+  let error = Message::parse_response(404, "<html>Not Found</html>", None).err().unwrap();
+
+  assert_eq!(error, GcmError::NotFound);
+  assert_eq!(error.status(), Some(404));
+  assert_eq!(error.to_string(), "NotFound: check the endpoint URL");
+}
+
+#[test]
+fn should_parse_error_as_payload_too_large() {
+  let error = Message::parse_response(413, "Request Entity Too Large", None).err().unwrap();
+
+  assert_eq!(error, GcmError::PayloadTooLarge { size: None, limit: None });
+  assert_eq!(error.status(), Some(413));
+  assert_eq!(error.to_string(), "PayloadTooLarge");
+}
+
+#[test]
+fn should_keep_status_of_unknown_errors() {
+  let error = Message::parse_response(403, "Forbidden", None).err().unwrap();
+
+  assert_eq!(error, GcmError::BadRequest { status: 403, body: "Forbidden".to_string(), request_body: None });
+  assert_eq!(error.status(), Some(403));
+}
+
+#[test]
+fn should_parse_json_error_body_as_invalid_request() {
+  let result = Message::parse_response(400,
+    r#"{"error": "InvalidParameters", "error_description": "topic name is invalid"}"#, None);
+
+  assert_eq!(result.err().unwrap(), GcmError::InvalidRequest(ErrorBody {
+    code: "InvalidParameters".to_string(),
+    description: Some("topic name is invalid".to_string()),
+    reason: None,
+    request_body: None
+  }));
+}
+
+#[test]
+fn should_parse_fcm_error_body_as_invalid_request() {
+  let result = Message::parse_response(400,
+    r#"{"error": {"code": 400, "message": "Invalid registration token", "status": "INVALID_ARGUMENT"}}"#, None);
+
+  assert_eq!(result.err().unwrap(), GcmError::InvalidRequest(ErrorBody {
+    code: "INVALID_ARGUMENT".to_string(),
+    description: Some("Invalid registration token".to_string()),
+    reason: None,
+    request_body: None
+  }));
+}
+
+#[test]
+fn should_parse_error_body() {
+  assert_eq!(ErrorBody::parse(r#"{"error": "InvalidParameters"}"#), Some(ErrorBody {
+    code: "InvalidParameters".to_string(),
+    description: None,
+    reason: None,
+    request_body: None
+  }));
+  assert_eq!(ErrorBody::parse(r#"{"error": {"code": 400}}"#).unwrap().code, "400");
+  assert_eq!(ErrorBody::parse(r#"{"message": "no error field"}"#), None);
+  assert_eq!(ErrorBody::parse("INVALID_REGISTRATION"), None);
+}
+
+#[test]
+fn should_parse_known_reasons_of_bad_requests() {
+  let reason = |body: &str| match Message::parse_response(400, body, None) {
+    Err(GcmError::InvalidRequest(error)) => error.reason,
+    other => panic!("expected InvalidRequest, got {:?}", other)
+  };
+
+  assert_eq!(reason("MissingRegistration"), Some(MessageResultError::MissingRegistration));
+  assert_eq!(reason("Error=InvalidRegistration\n"), Some(MessageResultError::InvalidRegistration));
+  assert_eq!(reason(r#"{"error": "InvalidPackageName"}"#), Some(MessageResultError::InvalidPackageName));
+  assert_eq!(reason(r#"{"error": "InvalidTtl"}"#), Some(MessageResultError::InvalidTtl));
+  assert_eq!(reason(r#"{"error": "InvalidDataKey"}"#), Some(MessageResultError::InvalidDataKey));
+  assert_eq!(reason(r#"{"error": "MessageTooBig", "error_description": "4097 bytes"}"#), Some(MessageResultError::MessageTooBig));
+  assert_eq!(reason(r#"{"error": "SomethingNew"}"#), None);
+}
+
+#[test]
+fn should_keep_the_raw_code_of_unknown_reasons() {
+  let error = ErrorBody::parse(r#"{"error": "SomethingNew"}"#).unwrap();
+
+  assert_eq!(error.code, "SomethingNew");
+  assert_eq!(error.reason, None);
 }
 
 #[test]
 fn should_parse_error_as_server_error() {
-  let result = Message::parse_response(StatusCode::InternalServerError, "Internal Server Error");
+  let result = Message::parse_response(500, "Internal Server Error", None);
+
+  assert!(result.is_err());
+  assert_eq!(result.err().unwrap(), GcmError::ServerError { status: 500, retry_after: None });
+}
+
+#[test]
+fn should_parse_retry_after_on_server_error() {
+  let result = Message::parse_response(503, "Unavailable", Some("120"));
 
   assert!(result.is_err());
-  assert_eq!(result.err().unwrap(), GcmError::ServerError);
+
+  let error = result.err().unwrap();
+
+  assert_eq!(error, GcmError::ServerError { status: 503, retry_after: Some(Duration::from_secs(120)) });
+  assert_eq!(error.retry_after(), Some(Duration::from_secs(120)));
+}
+
+#[test]
+fn should_parse_too_many_requests_as_rate_limited() {
+  let result = Message::parse_response(429, "", None);
+
+  assert_eq!(result.err().unwrap(), GcmError::RateLimited { retry_after: None });
+}
+
+#[test]
+fn should_parse_retry_after_when_rate_limited() {
+  let error = Message::parse_response(429, "", Some("60")).err().unwrap();
+
+  assert_eq!(error, GcmError::RateLimited { retry_after: Some(Duration::from_secs(60)) });
+  assert_eq!(error.retry_after(), Some(Duration::from_secs(60)));
+  assert_eq!(error.status(), Some(429));
+}
+
+#[test]
+fn should_ignore_malformed_retry_after() {
+  let result = Message::parse_response(503, "Unavailable", Some("soon"));
+
+  assert_eq!(result.err().unwrap(), GcmError::ServerError { status: 503, retry_after: None });
+}
+
+#[test]
+fn should_parse_retry_after_seconds() {
+  assert_eq!(retry_after::parse("30"), Some(Duration::from_secs(30)));
+  assert_eq!(retry_after::parse("-1"), None);
+}
+
+#[test]
+fn should_parse_retry_after_http_date() {
+  // Sun, 06 Nov 1994 08:49:37 GMT is 784111777 seconds after the epoch
+  let now = UNIX_EPOCH + Duration::from_secs(784111777 - 120);
+
+  assert_eq!(retry_after::parse_at("Sun, 06 Nov 1994 08:49:37 GMT", now),
+    Some(Duration::from_secs(120)));
+  assert_eq!(retry_after::parse_at("Sun, 06 Nov 1994 08:49:37 GMT", now + Duration::from_secs(600)),
+    Some(Duration::from_secs(0)));
+  assert_eq!(retry_after::parse_at("Sunday, 06-Nov-94 08:49:37 GMT", now), None);
+}
+
+#[test]
+fn should_ignore_retry_after_dates_past_year_9999() {
+  assert!(retry_after::parse("Fri, 31 Dec 9999 23:59:59 GMT").is_some());
+  assert_eq!(retry_after::parse("Mon, 01 Jan 10000 00:00:00 GMT"), None);
+  assert_eq!(retry_after::parse("Mon, 01 Jan 300000000000 00:00:00 GMT"), None);
+  assert_eq!(retry_after::parse(&format!("Mon, 01 Jan {} 00:00:00 GMT", u64::MAX)), None);
+}
+
+#[test]
+fn should_not_panic_on_huge_retry_after_dates() {
+  let result = Message::parse_response(503, "", Some("Mon, 01 Jan 300000000000 00:00:00 GMT"));
+
+  assert_eq!(result.err().unwrap(), GcmError::ServerError { status: 503, retry_after: None });
+}
+
+#[test]
+fn should_parse_real_multicast_id() {
+  let response = r#"{"multicast_id": 8000914484434486143, "success": 1, "failure": 0, "canonical_ids": 0,
+    "results": [{"message_id": "0:1516231452651543%e57df5c3f9fd7ecd"}]}"#;
+
+  let result = Message::parse_response(200, response, None).unwrap();
+
+  assert_eq!(result.multicast_id, Some(8000914484434486143u64));
 }
 
 #[test]
@@ -178,24 +551,29 @@ fn should_parse_successful_response() {
       "canonical_ids": 23,
       "results": [
         {
-          "message_id": 200000,
-          "registration_id": 200000,
-          "error": "error"
+          "message_id": "0:1516231452651543%e57df5c3f9fd7ecd",
+          "registration_id": "APA91bHun4MxP5egoKMwt2KZFBaFUH-1RYqx",
+          "error": "InvalidRegistration"
         }
       ]
     }
   "#;
-  let result = Message::parse_response(StatusCode::Ok, response);
+  let result = Message::parse_response(200, response, None);
 
   assert!(result.is_ok());
 
   let result = result.unwrap();
 
-  assert_eq!(result.message_id.unwrap(), 2000000);
+  assert_eq!(result.message_id.unwrap(), "2000000");
 
   let message_results = result.results.unwrap();
 
   assert_eq!(message_results.len(), 1);
+  assert_eq!(message_results[0].message_id,
+    Some("0:1516231452651543%e57df5c3f9fd7ecd".to_string()));
+  assert_eq!(message_results[0].registration_id,
+    Some("APA91bHun4MxP5egoKMwt2KZFBaFUH-1RYqx".to_string()));
+  assert_eq!(message_results[0].error, Some(MessageResultError::InvalidRegistration));
 }
 
 #[test]
@@ -210,13 +588,13 @@ fn should_parse_dry_run_message() {
       "results": [
         {
           "message_id": "fake_message_id",
-          "registration_id": 200000,
+          "registration_id": "APA91bHun4MxP5egoKMwt2KZFBaFUH-1RYqx",
           "error": "error"
         }
       ]
     }
   "#;
-  let result = Message::parse_response(StatusCode::Ok, response);
+  let result = Message::parse_response(200, response, None);
 
   assert!(result.is_ok());
 
@@ -225,13 +603,693 @@ fn should_parse_dry_run_message() {
   let message_results = result.results.unwrap();
 
   assert_eq!(message_results.len(), 1);
-  assert_eq!(message_results[0].message_id, None);
+  assert_eq!(message_results[0].message_id, Some("fake_message_id".to_string()));
 }
 
 #[test]
 fn should_parse_decode_errors_as_server_errors() {
-  let result = Message::parse_response(StatusCode::Ok, "Invalid JSON");
+  let result = Message::parse_response(200, "Invalid JSON", None);
 
-  assert!(result.is_err());
-  assert_eq!(result.err().unwrap(), GcmError::InvalidJsonBody);
-}
\ No newline at end of file
+  match result {
+    Err(GcmError::InvalidJsonBody(Some(_))) => (),
+    result => panic!("expected InvalidJsonBody, got {:?}", result)
+  }
+}
+
+#[test]
+fn should_find_unregistered_tokens() {
+  let response = r#"
+    {
+      "multicast_id": 216,
+      "success": 1,
+      "failure": 3,
+      "canonical_ids": 0,
+      "results": [
+        { "message_id": "1:0408" },
+        { "error": "NotRegistered" },
+        { "error": "Unavailable" },
+        { "error": "InvalidRegistration" }
+      ]
+    }
+  "#;
+  let result = Message::parse_response(200, response, None).unwrap();
+  let sent_ids = vec!["id1".to_string(), "id2".to_string(), "id3".to_string(), "id4".to_string()];
+
+  assert_eq!(result.unregistered_tokens(&sent_ids), vec!["id2".to_string(), "id4".to_string()]);
+}
+
+#[test]
+fn should_ignore_extra_ids_when_finding_unregistered_tokens() {
+  let response = r#"
+    {
+      "results": [
+        { "error": "NotRegistered" }
+      ]
+    }
+  "#;
+  let result = Message::parse_response(200, response, None).unwrap();
+  let sent_ids = vec!["id1".to_string(), "id2".to_string()];
+
+  assert_eq!(result.unregistered_tokens(&sent_ids), vec!["id1".to_string()]);
+  assert_eq!(result.unregistered_tokens(&[]), Vec::<String>::new());
+}
+
+#[test]
+fn should_find_retriable_tokens() {
+  let response = r#"
+    {
+      "results": [
+        { "message_id": "1:0408" },
+        { "error": "Unavailable" },
+        { "error": "NotRegistered" },
+        { "error": "InternalServerError" },
+        { "error": "MismatchSenderId" },
+        { "error": "DeviceMessageRateExceeded" },
+        { "error": "InvalidRegistration" },
+        { "error": "SomethingNew" }
+      ]
+    }
+  "#;
+  let result = Message::parse_response(200, response, None).unwrap();
+  let sent_ids: Vec<String> = (1..9).map(|i| format!("id{}", i)).collect();
+
+  assert_eq!(result.retriable_tokens(&sent_ids), vec!["id2".to_string(), "id4".to_string(), "id6".to_string()]);
+  assert_eq!(result.retriable_tokens(&sent_ids[..1]), Vec::<String>::new());
+}
+
+#[test]
+fn should_map_error_codes_to_message_result_errors() {
+  assert_eq!(MessageResultError::from("NotRegistered"), MessageResultError::NotRegistered);
+  assert_eq!(MessageResultError::from("MismatchSenderId"), MessageResultError::MismatchSenderId);
+  assert_eq!(MessageResultError::from("TopicsMessageRateExceeded"),
+    MessageResultError::TopicsMessageRateExceeded);
+  assert_eq!(MessageResultError::from("SomethingNew"),
+    MessageResultError::Other("SomethingNew".to_string()));
+  assert_eq!(MessageResultError::InvalidTtl.as_str(), "InvalidTtl");
+  assert_eq!(MessageResultError::Other("SomethingNew".to_string()).to_string(), "SomethingNew");
+}
+
+#[test]
+fn should_deserialize_message_result_errors() {
+  let errors: Vec<MessageResultError> =
+    serde_json::from_str(r#"["InvalidRegistration", "Unavailable", "SomethingNew"]"#).unwrap();
+
+  assert_eq!(errors, vec![
+    MessageResultError::InvalidRegistration,
+    MessageResultError::Unavailable,
+    MessageResultError::Other("SomethingNew".to_string())
+  ]);
+}
+
+#[test]
+fn should_summarize_failures() {
+  let response = r#"
+    {
+      "multicast_id": 216,
+      "success": 2,
+      "failure": 2,
+      "canonical_ids": 0,
+      "results": [
+        { "message_id": "1:0408" },
+        { "error": "Unavailable" },
+        { "message_id": "1:1516" },
+        { "error": "NotRegistered" }
+      ]
+    }
+  "#;
+  let result = Message::parse_response(200, response, None).unwrap();
+
+  assert!(!result.is_success());
+  assert_eq!(result.failed_indices(), vec![1, 3]);
+
+  let errors: Vec<Option<MessageResultError>> = result.failed_results().map(|r| r.error.clone()).collect();
+
+  assert_eq!(errors, vec![Some(MessageResultError::Unavailable), Some(MessageResultError::NotRegistered)]);
+}
+
+#[test]
+fn should_be_successful_without_failures() {
+  let response = r#"{ "multicast_id": 216, "success": 1, "failure": 0, "results": [{ "message_id": "1:0408" }] }"#;
+  let result = Message::parse_response(200, response, None).unwrap();
+
+  assert!(result.is_success());
+  assert!(result.failed_indices().is_empty());
+  assert_eq!(result.failed_results().count(), 0);
+}
+
+#[test]
+fn should_round_trip_owned_messages() {
+  let mut data = HashMap::new();
+  data.insert("my", "data");
+  let notification = NotificationBuilder::new("title")
+      .body("body")
+      .finalize();
+
+  let msg = Message::new("token")
+      .priority(Priority::High)
+      .collapse_key("key")
+      .data(data)
+      .notification(notification);
+  let json = serde_json::to_string(&msg).unwrap();
+
+  let owned: OwnedMessage = serde_json::from_str(&serde_json::to_string(&OwnedMessage::from(msg)).unwrap()).unwrap();
+
+  assert_eq!(serde_json::to_string(&owned).unwrap(), json);
+  assert_eq!(serde_json::to_string(&Message::from(&owned)).unwrap(), json);
+}
+
+#[test]
+fn should_send_owned_messages() {
+  let owned: OwnedMessage = serde_json::from_str(r#"{"to":"token","priority":"high","notification":{"title":"title"}}"#).unwrap();
+  let transport = MockTransport::new()
+      .respond(200, r#"{"message_id": "0:1"}"#);
+  let client = GcmClient::new().transport(transport.clone());
+
+  let response = client.send(&Message::from(&owned), "key").unwrap();
+
+  assert_eq!(response.message_id, Some("0:1".to_string()));
+  assert_eq!(transport.requests()[0].body,
+    br#"{"to":"token","priority":"high","notification":{"title":"title"}}"#.to_vec());
+}
+
+#[test]
+fn should_set_high_priority() {
+  let msg = Message::new("token").high_priority();
+
+  assert_eq!(msg.get_priority(), Some(Priority::High));
+}
+
+#[test]
+fn should_override_priority_with_high_priority() {
+  let msg = Message::new("token").priority(Priority::Normal).high_priority();
+
+  assert_eq!(msg.get_priority(), Some(Priority::High));
+}
+
+#[test]
+fn should_reject_delay_while_idle_on_high_priority() {
+  let msg = Message::new("token").high_priority().delay_while_idle(true);
+
+  assert_eq!(msg.validate(), Err(GcmError::InvalidMessage(
+    "`delay_while_idle` can't be set on a high priority message".to_string())));
+}
+
+#[test]
+fn should_allow_delay_while_idle_on_normal_priority() {
+  let msg = Message::new("token").content_available(true);
+
+  assert!(msg.clone().priority(Priority::Normal).delay_while_idle(true).validate().is_ok());
+  assert!(msg.clone().delay_while_idle(true).validate().is_ok());
+  assert!(msg.high_priority().delay_while_idle(false).validate().is_ok());
+}
+
+#[test]
+fn should_keep_raw_response_body() {
+  let response = r#"{"message_id": "0:1", "unknown_field": true}"#;
+
+  let result = Message::parse_response(200, response, None).unwrap();
+
+  assert_eq!(result.raw, response);
+}
+
+#[derive(Serialize)]
+struct Score {
+  home: u32,
+  away: u32,
+  teams: Vec<&'static str>
+}
+
+#[test]
+fn should_set_data_from_struct() {
+  let msg = Message::new("token")
+    .data_struct(Score { home: 250, away: 248, teams: vec!["India", "Australia"] })
+    .unwrap();
+
+  let j = serde_json::to_value(&msg).unwrap();
+
+  assert_eq!(j["data"]["home"], 250);
+  assert_eq!(j["data"]["away"], 248);
+  assert_eq!(j["data"]["teams"][1], "Australia");
+}
+
+#[test]
+fn should_reject_data_struct_that_is_not_an_object() {
+  assert_eq!(Message::new("token").data_struct(vec![1, 2]).err().unwrap(), GcmError::InvalidJsonBody(None));
+  assert_eq!(Message::new("token").data_struct("text").err().unwrap(), GcmError::InvalidJsonBody(None));
+}
+
+#[test]
+fn should_reject_invalid_notification_color() {
+  let nm = NotificationBuilder::new("title").color("blue").finalize();
+  let msg = Message::new("token").notification(nm);
+
+  assert_eq!(msg.validate(), Err(GcmError::InvalidMessage(
+    "invalid color `blue`, expected #rgb or #rrggbb".to_string())));
+}
+
+#[test]
+fn should_send_to_topic() {
+  let msg = Message::to_topic("India-vs_Australia.2017~%20").unwrap();
+
+  assert_eq!(msg.get_to(), "/topics/India-vs_Australia.2017~%20");
+  assert!(msg.content_available(true).validate().is_ok());
+}
+
+#[test]
+fn should_reject_invalid_topic_names() {
+  for name in &["", "/topics/cricket", "india vs australia", "cricket!", "crické"] {
+    assert_eq!(Message::to_topic(name).err().unwrap(),
+      GcmError::InvalidMessage(format!("invalid topic name `{}`", name)));
+  }
+}
+
+#[test]
+fn should_set_webpush() {
+  let msg = Message::new("token");
+  let j = serde_json::to_value(&msg).unwrap();
+
+  assert!(j.get("webpush").is_none());
+
+  let msg = msg.webpush(WebpushConfig::new().ttl(60));
+  let j = serde_json::to_value(&msg).unwrap();
+
+  assert_eq!(j["webpush"], json!({ "headers": { "TTL": "60" } }));
+  assert_eq!(msg.get_webpush(), Some(&WebpushConfig::new().ttl(60)));
+}
+
+#[test]
+fn should_set_apns() {
+  let msg = Message::new("token");
+  let j = serde_json::to_value(&msg).unwrap();
+
+  assert!(j.get("apns").is_none());
+
+  let msg = msg.apns(ApnsConfig::new().priority(5));
+  let j = serde_json::to_value(&msg).unwrap();
+
+  assert_eq!(j["apns"], json!({ "headers": { "apns-priority": "5" } }));
+  assert_eq!(msg.get_apns(), Some(&ApnsConfig::new().priority(5)));
+}
+
+#[test]
+fn should_set_android_alongside_legacy_fields() {
+  let msg = Message::new("token")
+    .collapse_key("legacy")
+    .android(AndroidConfig::new().collapse_key("v1"));
+
+  let j = serde_json::to_value(&msg).unwrap();
+
+  assert_eq!(j["collapse_key"], "legacy");
+  assert_eq!(j["android"], json!({ "collapse_key": "v1" }));
+  assert_eq!(msg.get_android().and_then(|android| android.get_collapse_key()), Some("v1"));
+}
+
+#[test]
+fn should_serialize_content_available_as_bool() {
+  let j = serde_json::to_value(&Message::new("token")).unwrap();
+
+  assert!(j.get("content_available").is_none());
+
+  let j = serde_json::to_string(&Message::new("token").content_available(true)).unwrap();
+
+  assert_eq!(j, r#"{"to":"token","content_available":true}"#);
+
+  let j = serde_json::to_string(&Message::new("token").content_available(false)).unwrap();
+
+  assert_eq!(j, r#"{"to":"token","content_available":false}"#);
+}
+
+#[test]
+fn should_validate_collapse_key_length() {
+  let longest = "k".repeat(MAX_COLLAPSE_KEY_LENGTH);
+  let too_long = "k".repeat(MAX_COLLAPSE_KEY_LENGTH + 1);
+
+  assert!(Message::new("token").content_available(true).collapse_key(&longest).validate().is_ok());
+  assert_eq!(Message::new("token").collapse_key("").validate(), Err(GcmError::InvalidMessage(
+    "`collapse_key` must be between 1 and 64 bytes, got 0".to_string())));
+  assert_eq!(Message::new("token").collapse_key(&too_long).validate(), Err(GcmError::InvalidMessage(
+    "`collapse_key` must be between 1 and 64 bytes, got 65".to_string())));
+}
+
+#[test]
+fn should_display_response_summary() {
+  let response = r#"{"multicast_id": 7, "success": 1, "failure": 1, "canonical_ids": 0,
+                     "results": [{"message_id": "0:1"}, {"error": "NotRegistered"}]}"#;
+
+  let result = Message::parse_response(200, response, None).unwrap();
+
+  assert_eq!(result.to_string(), "multicast_id=7 success=1 failure=1 canonical_ids=0");
+  assert_eq!(result.summary(), ResponseSummary { success: 1, failure: 1, canonical_ids: 0 });
+}
+
+#[test]
+fn should_display_single_message_response() {
+  let result = Message::parse_response(200, r#"{"message_id": "0:1"}"#, None).unwrap();
+
+  assert_eq!(result.to_string(), "message_id=0:1");
+  assert_eq!(result.summary(), ResponseSummary::default());
+}
+
+#[test]
+fn should_validate_restricted_package_name() {
+  for name in &["com.example.app", "com.example_2.App", "io.a"] {
+    assert!(Message::new("token").content_available(true).restricted_package_name(name).validate().is_ok());
+  }
+
+  for name in &["com..app", "1com.example", "com", "com.example.", ".com.example", "com.1example", "com.exa-mple", ""] {
+    assert_eq!(Message::new("token").restricted_package_name(name).validate(), Err(GcmError::InvalidMessage(format!(
+      "invalid `restricted_package_name` `{}`, expected a name like `com.example.app`", name))));
+  }
+}
+
+#[test]
+fn should_return_canonical_updates() {
+  let response = r#"{"multicast_id": 1, "success": 3, "failure": 0, "canonical_ids": 1,
+                     "results": [{"message_id": "0:1"},
+                                 {"message_id": "0:2", "registration_id": "new_id2"},
+                                 {"message_id": "0:3"}]}"#;
+  let sent_ids = vec!["id1".to_string(), "id2".to_string(), "id3".to_string()];
+
+  let result = Message::parse_response(200, response, None).unwrap();
+
+  assert_eq!(result.canonical_updates(&sent_ids), vec![("id2".to_string(), "new_id2".to_string())]);
+}
+
+#[test]
+fn should_return_no_canonical_updates_without_results() {
+  let result = Message::parse_response(200, r#"{"message_id": "0:1"}"#, None).unwrap();
+
+  assert!(result.canonical_updates(&["id1".to_string()]).is_empty());
+}
+
+#[test]
+fn should_return_results_by_token() {
+  let response = r#"{"multicast_id": 1, "success": 1, "failure": 1, "canonical_ids": 0,
+                     "results": [{"message_id": "0:1"}, {"error": "NotRegistered"}]}"#;
+  let sent_ids = vec!["id1".to_string(), "id2".to_string()];
+
+  let result = Message::parse_response(200, response, None).unwrap();
+  let by_token = result.results_by_token(&sent_ids).unwrap();
+
+  assert_eq!(by_token.len(), 2);
+  assert_eq!(by_token["id1"].message_id, Some("0:1".to_string()));
+  assert_eq!(by_token["id2"].error, Some(MessageResultError::NotRegistered));
+}
+
+#[test]
+fn should_fail_results_by_token_on_length_mismatch() {
+  let response = r#"{"results": [{"message_id": "0:1"}]}"#;
+
+  let result = Message::parse_response(200, response, None).unwrap();
+  let error = result.results_by_token(&["id1".to_string(), "id2".to_string()]).err().unwrap();
+
+  assert_eq!(error, GcmError::ResultCountMismatch { sent: 2, results: 1 });
+  assert_eq!(error.to_string(), "ResultCountMismatch: sent 2 ids, got 1 results");
+}
+
+#[test]
+fn should_reject_reserved_data_keys() {
+  for key in &["from", "message_type", "gcm.foo", "google.bar", "googleapis"] {
+    let mut data = HashMap::new();
+    data.insert(*key, "value");
+
+    assert_eq!(Message::new("token").data(data).validate(), Err(GcmError::InvalidDataKey(key.to_string())));
+  }
+}
+
+#[test]
+fn should_accept_data_keys_that_are_not_reserved() {
+  let mut data = HashMap::new();
+  data.insert("from_user", "value");
+  data.insert("my_gcm", "value");
+
+  assert!(Message::new("token").data(data).validate().is_ok());
+}
+
+#[test]
+fn should_nest_analytics_label_in_fcm_options() {
+  let msg = Message::new("token").analytics_label("spring_sale-2017");
+
+  let j = serde_json::to_value(&msg).unwrap();
+
+  assert_eq!(j["fcm_options"], json!({ "analytics_label": "spring_sale-2017" }));
+  assert_eq!(msg.get_analytics_label(), Some("spring_sale-2017"));
+  assert!(msg.content_available(true).validate().is_ok());
+  assert!(serde_json::to_value(&Message::new("token")).unwrap().get("fcm_options").is_none());
+}
+
+#[test]
+fn should_reject_invalid_analytics_label() {
+  let too_long = "a".repeat(MAX_ANALYTICS_LABEL_LENGTH + 1);
+
+  assert!(Message::new("token").content_available(true).analytics_label(&too_long[1..]).validate().is_ok());
+
+  for label in &[&too_long[..], "", "spring sale", "sale!"] {
+    assert_eq!(Message::new("token").analytics_label(label).validate(), Err(GcmError::InvalidMessage(format!(
+      "invalid `analytics_label` `{}`, expected 1 to 50 of `[a-zA-Z0-9-_.~%]`", label))));
+  }
+}
+
+#[test]
+fn should_redact_tokens_in_debug_output() {
+  let msg = Message::new("APA91bHun4MxP5egoKMwt2KZFBaFUH").dry_run(true);
+
+  let debug = format!("{:?}", msg);
+
+  assert!(debug.starts_with(r#"Message { to: "APA91b...", registration_ids: None, condition: None,"#));
+  assert!(debug.contains("dry_run: Some(true)"));
+  assert!(!debug.contains("APA91bHun4"));
+
+  let msg = Message::new("").registration_ids(vec!["abcdefghijk", "lmnopqrstuv"]);
+
+  assert!(format!("{:?}", msg).contains(r#"registration_ids: Some(["abcdef...", "lmnopq..."])"#));
+  assert!(format!("{:?}", Message::new("/topics/cricket")).contains(r#"to: "/topics/cricket""#));
+}
+
+#[test]
+fn should_not_leak_the_api_key_in_errors() {
+  let transport = MockTransport::new()
+      .respond(401, "Unauthorized");
+  let client = GcmClient::new().transport(transport);
+
+  let error = client.send(&Message::new("token").content_available(true), "secret-api-key").err().unwrap();
+
+  assert_eq!(error, GcmError::Unauthorized);
+  assert!(!format!("{:?}", error).contains("secret-api-key"));
+  assert!(!error.to_string().contains("secret-api-key"));
+}
+
+#[test]
+fn should_convert_messages_to_fcm_v1() {
+  let mut data = HashMap::new();
+  data.insert("score".to_string(), json!(42));
+  data.insert("message".to_string(), json!("Howdy!"));
+  let notification = NotificationBuilder::new("title")
+      .body("body")
+      .color("#ff0000")
+      .android_channel_id("updates")
+      .finalize();
+  let msg = Message::new("token")
+      .collapse_key("score")
+      .priority(Priority::High)
+      .time_to_live(60)
+      .dry_run(true)
+      .data_json(data)
+      .notification(notification)
+      .analytics_label("score");
+
+  let body: serde_json::Value = serde_json::from_str(&v1::body(&msg).unwrap()).unwrap();
+
+  assert_eq!(body, json!({
+    "validate_only": true,
+    "message": {
+      "token": "token",
+      "data": { "score": "42", "message": "Howdy!" },
+      "notification": { "title": "title", "body": "body" },
+      "android": {
+        "collapse_key": "score",
+        "priority": "HIGH",
+        "ttl": "60s",
+        "notification": { "color": "#ff0000", "channel_id": "updates" }
+      },
+      "fcm_options": { "analytics_label": "score" }
+    }
+  }));
+}
+
+#[test]
+fn should_prefer_android_config_when_converting_to_fcm_v1() {
+  let msg = Message::to_topic("cricket").unwrap()
+      .collapse_key("legacy")
+      .android(AndroidConfig::new().collapse_key("v1"));
+
+  let body: serde_json::Value = serde_json::from_str(&v1::body(&msg).unwrap()).unwrap();
+
+  assert_eq!(body, json!({ "message": { "topic": "cricket", "android": { "collapse_key": "v1" } } }));
+}
+
+#[test]
+fn should_reject_registration_ids_for_fcm_v1() {
+  let msg = Message::new("").registration_ids(vec!["id1", "id2"]);
+
+  assert_eq!(v1::body(&msg), Err(GcmError::InvalidMessage(
+    "FCM v1 can't send to `registration_ids`, send to each token instead".to_string())));
+}
+
+#[test]
+fn should_serialize_to_wire_json() {
+  let mut data = HashMap::new();
+  data.insert("message", "Howdy!");
+  let notification = NotificationBuilder::new("title").body("body").finalize();
+  let msg = Message::new("token")
+      .collapse_key("greeting")
+      .priority(Priority::High)
+      .time_to_live(60)
+      .data(data)
+      .notification(notification);
+
+  assert_eq!(msg.to_json().unwrap(), concat!(r#"{"to":"token","collapse_key":"greeting","priority":"high","#,
+    r#""time_to_live":60,"data":{"message":"Howdy!"},"notification":{"title":"title","body":"body"}}"#));
+}
+
+#[test]
+fn should_send_the_same_json_as_to_json() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"message_id": "0:1"}"#);
+  let client = GcmClient::new().transport(transport.clone());
+  let msg = Message::to_topic("cricket").unwrap().content_available(true).dry_run(true);
+
+  client.send(&msg, "key").unwrap();
+
+  assert_eq!(transport.requests()[0].body, msg.to_json().unwrap().into_bytes());
+}
+
+#[test]
+fn should_skip_empty_data() {
+  let msg = Message::new("token").data(HashMap::new());
+
+  assert_eq!(msg.to_json().unwrap(), r#"{"to":"token"}"#);
+  assert_eq!(serde_json::to_string(&OwnedMessage::from(msg)).unwrap(), r#"{"to":"token"}"#);
+}
+
+#[test]
+fn should_convert_json_errors_to_invalid_json_body() {
+  fn parse(json: &str) -> Result<serde_json::Value, GcmError> {
+    Ok(serde_json::from_str(json)?)
+  }
+
+  let error = parse("{").err().unwrap();
+
+  assert_eq!(error.to_string(), "InvalidJsonBody: EOF while parsing an object at line 1 column 1");
+  assert!(error.source().unwrap().is::<serde_json::Error>());
+  assert_eq!(parse("{}"), Ok(json!({})));
+}
+
+#[test]
+fn should_convert_transport_errors() {
+  assert_eq!(GcmError::from(TransportError::new("connection refused")),
+    GcmError::HttpError(TransportError::new("connection refused")));
+  assert_eq!(GcmError::from(TransportError::tls("bad certificate")),
+    GcmError::TlsInitError(TransportError::tls("bad certificate")));
+}
+
+#[test]
+fn should_treat_topic_message_id_as_success() {
+  let result = Message::parse_response(200, r#"{"message_id": 6177433305390290000}"#, None).unwrap();
+
+  assert!(result.is_success());
+  assert_eq!(result.message_id, Some("6177433305390290000".to_string()));
+}
+
+#[test]
+fn should_parse_topic_error_as_rejected_message() {
+  let error = Message::parse_response(200, r#"{"error": "TopicsMessageRateExceeded"}"#, None).err().unwrap();
+
+  assert_eq!(error, GcmError::MessageRejected(MessageResultError::TopicsMessageRateExceeded));
+  assert_eq!(error.to_string(), "MessageRejected: TopicsMessageRateExceeded");
+
+  let error = Message::parse_response(200, r#"{"error": "InvalidTopic"}"#, None).err().unwrap();
+
+  assert_eq!(error, GcmError::MessageRejected(MessageResultError::Other("InvalidTopic".to_string())));
+}
+
+#[test]
+fn should_reject_condition_send_with_top_level_error() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"error":"TopicsMessageRateExceeded"}"#);
+  let client = GcmClient::new().transport(transport);
+
+  let result = client.send(&Message::new("").condition("'cricket' in topics").content_available(true), "key");
+
+  assert_eq!(result.err().unwrap(), GcmError::MessageRejected(MessageResultError::TopicsMessageRateExceeded));
+}
+
+#[test]
+fn should_deserialize_typed_top_level_error() {
+  let response: GcmResponse = serde_json::from_str(r#"{"error": "InvalidRegistration"}"#).unwrap();
+
+  assert_eq!(response.error, Some(MessageResultError::InvalidRegistration));
+  assert!(!response.is_success());
+  assert_eq!(response.to_string(), "error=InvalidRegistration");
+}
+
+#[test]
+fn should_reject_empty_payload() {
+  let empty = Err(GcmError::InvalidMessage(
+    "one of `notification`, `data`, `content_available`, `webpush`, `apns` or `android` must be set".to_string()));
+
+  assert_eq!(Message::new("token").validate(), empty);
+  assert_eq!(Message::new("token").data(HashMap::new()).validate(), empty);
+  assert_eq!(Message::new("token").content_available(false).collapse_key("update").validate(), empty);
+
+  assert!(Message::new("token").notification(NotificationBuilder::new("title").finalize()).validate().is_ok());
+  assert!(Message::new("token").content_available(true).validate().is_ok());
+  assert!(Message::new("token").android(AndroidConfig::new().collapse_key("update")).validate().is_ok());
+}
+
+#[test]
+fn should_reject_collapse_key_on_high_priority_content_available() {
+  let msg = Message::new("token").collapse_key("update").high_priority().content_available(true);
+
+  assert_eq!(msg.validate(), Err(GcmError::InvalidMessage(
+    "`collapse_key` can't be set on a high priority `content_available` message, which isn't collapsible; \
+     use normal priority or drop the `collapse_key`".to_string())));
+}
+
+#[test]
+fn should_allow_collapse_key_without_the_conflict() {
+  let msg = Message::new("token").collapse_key("update").content_available(true);
+
+  assert!(msg.clone().priority(Priority::Normal).validate().is_ok());
+  assert!(msg.validate().is_ok());
+  assert!(Message::new("token").collapse_key("update").high_priority()
+    .notification(NotificationBuilder::new("title").finalize()).validate().is_ok());
+}
+
+#[test]
+fn should_keep_the_message_after_send_ref() {
+  let msg = Message::new("token");
+  let empty = GcmError::InvalidMessage(
+    "one of `notification`, `data`, `content_available`, `webpush`, `apns` or `android` must be set".to_string());
+
+  assert_eq!(msg.send_ref("key").err().unwrap(), empty);
+  assert_eq!(msg.send_ref("key").err().unwrap(), empty);
+  assert_eq!(msg.get_to(), "token");
+}
+
+#[test]
+fn should_send_the_same_message_twice() {
+  let transport = MockTransport::new()
+      .respond(200, r#"{"message_id": "0:1"}"#);
+  let client = GcmClient::new().transport(transport.clone());
+  let msg = Message::new("token").content_available(true);
+
+  client.send(&msg, "key").unwrap();
+  client.send(&msg, "key").unwrap();
+
+  let requests = transport.requests();
+
+  assert_eq!(requests.len(), 2);
+  assert_eq!(requests[0].body, requests[1].body);
+  assert_eq!(requests[0].body, msg.to_json().unwrap().into_bytes());
+}
@@ -0,0 +1,126 @@
+use {Message, GcmError};
+use serde_json::{to_string, to_value, Map, Value};
+
+// The notification fields FCM HTTP v1 shows on every platform.
+const COMMON_NOTIFICATION_FIELDS: &'static [(&'static str, &'static str)] = &[
+  ("title", "title"),
+  ("body", "body"),
+  ("image", "image"),
+];
+
+// The notification fields FCM HTTP v1 only knows for Android, with their
+// v1 names.
+const ANDROID_NOTIFICATION_FIELDS: &'static [(&'static str, &'static str)] = &[
+  ("icon", "icon"),
+  ("color", "color"),
+  ("sound", "sound"),
+  ("tag", "tag"),
+  ("click_action", "click_action"),
+  ("body_loc_key", "body_loc_key"),
+  ("body_loc_args", "body_loc_args"),
+  ("title_loc_key", "title_loc_key"),
+  ("title_loc_args", "title_loc_args"),
+  ("android_channel_id", "channel_id"),
+  ("notification_priority", "notification_priority"),
+  ("default_sound", "default_sound"),
+  ("default_vibrate_timings", "default_vibrate_timings"),
+  ("vibrate_timings", "vibrate_timings"),
+  ("event_time", "event_time"),
+  ("light_settings", "light_settings"),
+];
+
+// The body of an FCM HTTP v1 request for a message, converted from its
+// legacy JSON. The legacy Android fields move to `android`, unless the
+// `AndroidConfig` sets them, and `dry_run` becomes `validate_only`. v1 has
+// no `content_available` or `delay_while_idle`, and sends to one token at
+// a time, so `registration_ids` are rejected.
+pub fn body(message: &Message) -> Result<String, GcmError> {
+  let legacy = match to_value(message)? {
+    Value::Object(legacy) => legacy,
+    _ => return Err(GcmError::InvalidJsonBody(None))
+  };
+  let mut v1 = Map::new();
+
+  if legacy.contains_key("registration_ids") {
+    return Err(GcmError::InvalidMessage(
+      "FCM v1 can't send to `registration_ids`, send to each token instead".to_string()));
+  }
+
+  if let Some(to) = legacy.get("to").and_then(Value::as_str) {
+    if to.starts_with("/topics/") {
+      v1.insert("topic".to_string(), Value::String(to["/topics/".len()..].to_string()));
+    } else {
+      v1.insert("token".to_string(), Value::String(to.to_string()));
+    }
+  }
+  copy(&legacy, &mut v1, "condition");
+
+  // v1 only takes strings as data values.
+  if let Some(&Value::Object(ref data)) = legacy.get("data") {
+    let data = data.iter()
+      .map(|(key, value)| (key.clone(), Value::String(match *value {
+        Value::String(ref value) => value.clone(),
+        ref value => value.to_string()
+      })))
+      .collect();
+    v1.insert("data".to_string(), Value::Object(data));
+  }
+
+  let mut android = match legacy.get("android") {
+    Some(&Value::Object(ref android)) => android.clone(),
+    _ => Map::new()
+  };
+  fill(&mut android, "collapse_key", legacy.get("collapse_key").cloned());
+  fill(&mut android, "priority", legacy.get("priority").and_then(Value::as_str)
+    .map(|priority| Value::String(priority.to_uppercase())));
+  fill(&mut android, "ttl", legacy.get("time_to_live").and_then(Value::as_i64)
+    .map(|time_to_live| Value::String(format!("{}s", time_to_live))));
+  fill(&mut android, "restricted_package_name", legacy.get("restricted_package_name").cloned());
+
+  if let Some(&Value::Object(ref notification)) = legacy.get("notification") {
+    let common = pick(notification, COMMON_NOTIFICATION_FIELDS);
+    if !common.is_empty() {
+      v1.insert("notification".to_string(), Value::Object(common));
+    }
+
+    let android_notification = pick(notification, ANDROID_NOTIFICATION_FIELDS);
+    if !android_notification.is_empty() {
+      fill(&mut android, "notification", Some(Value::Object(android_notification)));
+    }
+  }
+
+  if !android.is_empty() {
+    v1.insert("android".to_string(), Value::Object(android));
+  }
+  copy(&legacy, &mut v1, "webpush");
+  copy(&legacy, &mut v1, "apns");
+  copy(&legacy, &mut v1, "fcm_options");
+
+  let mut body = Map::new();
+  if legacy.get("dry_run") == Some(&Value::Bool(true)) {
+    body.insert("validate_only".to_string(), Value::Bool(true));
+  }
+  body.insert("message".to_string(), Value::Object(v1));
+
+  Ok(to_string(&body)?)
+}
+
+fn copy(from: &Map<String, Value>, to: &mut Map<String, Value>, key: &str) {
+  if let Some(value) = from.get(key) {
+    to.insert(key.to_string(), value.clone());
+  }
+}
+
+// Set `key`, unless it is set already.
+fn fill(map: &mut Map<String, Value>, key: &str, value: Option<Value>) {
+  if let Some(value) = value {
+    map.entry(key.to_string()).or_insert(value);
+  }
+}
+
+// The `fields` of `from` that are set, renamed.
+fn pick(from: &Map<String, Value>, fields: &[(&str, &str)]) -> Map<String, Value> {
+  fields.iter()
+    .filter_map(|&(legacy, v1)| from.get(legacy).map(|value| (v1.to_string(), value.clone())))
+    .collect()
+}
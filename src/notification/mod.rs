@@ -1,33 +1,406 @@
 #[cfg(test)]
 mod tests;
 
+use std::borrow::Cow;
+use std::time::Duration;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+use message::response::GcmError;
+
+/// The priority of a notification on Android, distinct from the priority
+/// of the message carrying it.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum NotificationPriority {
+  #[serde(rename = "PRIORITY_MIN")]
+  Min,
+  #[serde(rename = "PRIORITY_LOW")]
+  Low,
+  #[serde(rename = "PRIORITY_DEFAULT")]
+  Default,
+  #[serde(rename = "PRIORITY_HIGH")]
+  High,
+  #[serde(rename = "PRIORITY_MAX")]
+  Max
+}
+
+/// The color and blink rate of the notification LED, for
+/// `NotificationBuilder::light_settings`.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct LightSettings {
+  color: LightColor,
+  light_on_duration: String,
+  light_off_duration: String,
+}
+
+impl LightSettings {
+  /// Blink the LED in the given color, in #rgb or #rrggbb format, on and
+  /// off for the given durations. Returns `InvalidMessage` for a
+  /// malformed color.
+  pub fn new(color: &str, on: Duration, off: Duration) -> Result<LightSettings, GcmError> {
+    if !is_valid_color(color) {
+      return Err(GcmError::InvalidMessage(format!("invalid color `{}`, expected #rgb or #rrggbb", color)));
+    }
+
+    let hex: Vec<u8> = color[1..].chars()
+      .map(|c| c.to_digit(16).unwrap() as u8)
+      .collect();
+    let channel = |i: usize| if hex.len() == 3 { hex[i] * 17 } else { hex[2 * i] * 16 + hex[2 * i + 1] };
+
+    Ok(LightSettings {
+      color: LightColor { red: channel(0), green: channel(1), blue: channel(2) },
+      light_on_duration: format_duration(on),
+      light_off_duration: format_duration(off),
+    })
+  }
+}
+
+// FCM takes the color channels as fractions between 0 and 1.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+struct LightColor {
+  red: u8,
+  green: u8,
+  blue: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Rgba {
+  #[serde(default)]
+  red: f32,
+  #[serde(default)]
+  green: f32,
+  #[serde(default)]
+  blue: f32,
+  #[serde(default)]
+  alpha: f32,
+}
+
+impl Serialize for LightColor {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+      where S: Serializer {
+    let fraction = |channel: u8| f32::from(channel) / 255.0;
+
+    Rgba {
+      red: fraction(self.red),
+      green: fraction(self.green),
+      blue: fraction(self.blue),
+      alpha: 1.0,
+    }.serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for LightColor {
+  fn deserialize<D>(deserializer: D) -> Result<LightColor, D::Error>
+      where D: Deserializer<'de> {
+    let rgba = Rgba::deserialize(deserializer)?;
+    let channel = |fraction: f32| (fraction.max(0.0).min(1.0) * 255.0).round() as u8;
+
+    Ok(LightColor {
+      red: channel(rgba.red),
+      green: channel(rgba.green),
+      blue: channel(rgba.blue),
+    })
+  }
+}
+
 /// This struct represents a GCM notification. Use the 
 /// corresponding `NotificationBuilder` to get an instance. You can then use 
 /// this notification instance when sending a GCM message.
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct Notification<'a> {
-  title: &'a str,
   #[serde(skip_serializing_if = "Option::is_none")]
-  body: Option<&'a str>,
-  icon: &'a str,
+  title: Option<Cow<'a, str>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  body: Option<Cow<'a, str>>,
   #[serde(skip_serializing_if = "Option::is_none")]
-  sound: Option<&'a str>,
+  icon: Option<Cow<'a, str>>,
   #[serde(skip_serializing_if = "Option::is_none")]
-  badge: Option<&'a str>,
+  sound: Option<Cow<'a, str>>,
   #[serde(skip_serializing_if = "Option::is_none")]
-  tag: Option<&'a str>,
+  badge: Option<Cow<'a, str>>,
   #[serde(skip_serializing_if = "Option::is_none")]
-  color: Option<&'a str>,
+  tag: Option<Cow<'a, str>>,
   #[serde(skip_serializing_if = "Option::is_none")]
-  click_action: Option<&'a str>,
+  color: Option<Cow<'a, str>>,
   #[serde(skip_serializing_if = "Option::is_none")]
-  body_loc_key: Option<&'a str>,
+  click_action: Option<Cow<'a, str>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  body_loc_key: Option<Cow<'a, str>>,
   #[serde(skip_serializing_if = "Option::is_none")]
   body_loc_args: Option<Vec<String>>,
   #[serde(skip_serializing_if = "Option::is_none")]
-  title_loc_key: Option<&'a str>,
+  title_loc_key: Option<Cow<'a, str>>,
   #[serde(skip_serializing_if = "Option::is_none")]
   title_loc_args: Option<Vec<String>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  mutable_content: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  android_channel_id: Option<Cow<'a, str>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  image: Option<Cow<'a, str>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  notification_priority: Option<NotificationPriority>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  default_sound: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  default_vibrate_timings: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  vibrate_timings: Option<Vec<String>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  event_time: Option<Cow<'a, str>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  light_settings: Option<LightSettings>,
+}
+
+impl<'a> Notification<'a> {
+  /// The title of the notification, if set.
+  pub fn title(&self) -> Option<&str> {
+    self.title.as_ref().map(|title| &**title)
+  }
+
+  /// The body of the notification, if set.
+  pub fn body(&self) -> Option<&str> {
+    self.body.as_ref().map(|body| &**body)
+  }
+
+  /// The notification icon, if set.
+  pub fn icon(&self) -> Option<&str> {
+    self.icon.as_ref().map(|icon| &**icon)
+  }
+
+  /// The sound to be played, if set.
+  pub fn sound(&self) -> Option<&str> {
+    self.sound.as_ref().map(|sound| &**sound)
+  }
+
+  /// The badge for iOS notifications, if set.
+  pub fn badge(&self) -> Option<&str> {
+    self.badge.as_ref().map(|badge| &**badge)
+  }
+
+  /// The tag, if set.
+  pub fn tag(&self) -> Option<&str> {
+    self.tag.as_ref().map(|tag| &**tag)
+  }
+
+  /// The color of the icon, if set.
+  pub fn color(&self) -> Option<&str> {
+    self.color.as_ref().map(|color| &**color)
+  }
+
+  /// The click action, if set.
+  pub fn click_action(&self) -> Option<&str> {
+    self.click_action.as_ref().map(|click_action| &**click_action)
+  }
+
+  /// The body key string for localization, if set.
+  pub fn body_loc_key(&self) -> Option<&str> {
+    self.body_loc_key.as_ref().map(|body_loc_key| &**body_loc_key)
+  }
+
+  /// The values for the format specifiers in the body string, if set.
+  pub fn body_loc_args(&self) -> Option<&[String]> {
+    self.body_loc_args.as_ref().map(|body_loc_args| &body_loc_args[..])
+  }
+
+  /// The title key string for localization, if set.
+  pub fn title_loc_key(&self) -> Option<&str> {
+    self.title_loc_key.as_ref().map(|title_loc_key| &**title_loc_key)
+  }
+
+  /// The values for the format specifiers in the title string, if set.
+  pub fn title_loc_args(&self) -> Option<&[String]> {
+    self.title_loc_args.as_ref().map(|title_loc_args| &title_loc_args[..])
+  }
+
+  /// The iOS `mutable-content` flag, if set.
+  pub fn mutable_content(&self) -> Option<bool> {
+    self.mutable_content
+  }
+
+  /// The Android notification channel, if set.
+  pub fn android_channel_id(&self) -> Option<&str> {
+    self.android_channel_id.as_ref().map(|android_channel_id| &**android_channel_id)
+  }
+
+  /// The URL of the big picture image, if set.
+  pub fn image(&self) -> Option<&str> {
+    self.image.as_ref().map(|image| &**image)
+  }
+
+  /// The Android notification priority, if set.
+  pub fn notification_priority(&self) -> Option<NotificationPriority> {
+    self.notification_priority
+  }
+
+  /// Whether to use the default sound, if set.
+  pub fn default_sound(&self) -> Option<bool> {
+    self.default_sound
+  }
+
+  /// Whether to use the default vibration pattern, if set.
+  pub fn default_vibrate_timings(&self) -> Option<bool> {
+    self.default_vibrate_timings
+  }
+
+  /// The vibration pattern, formatted like `"3.5s"`, if set.
+  pub fn vibrate_timings(&self) -> Option<&[String]> {
+    self.vibrate_timings.as_ref().map(|vibrate_timings| &vibrate_timings[..])
+  }
+
+  /// The time of the event in the notification, if set.
+  pub fn event_time(&self) -> Option<&str> {
+    self.event_time.as_ref().map(|event_time| &**event_time)
+  }
+
+  /// The LED settings, if set.
+  pub fn light_settings(&self) -> Option<&LightSettings> {
+    self.light_settings.as_ref()
+  }
+
+  /// Copy any borrowed strings, so that the notification can outlive them.
+  /// Useful to store or queue a notification.
+  pub fn into_owned(self) -> Notification<'static> {
+    Notification {
+      title: self.title.map(owned),
+      body: self.body.map(owned),
+      icon: self.icon.map(owned),
+      sound: self.sound.map(owned),
+      badge: self.badge.map(owned),
+      tag: self.tag.map(owned),
+      color: self.color.map(owned),
+      click_action: self.click_action.map(owned),
+      body_loc_key: self.body_loc_key.map(owned),
+      body_loc_args: self.body_loc_args,
+      title_loc_key: self.title_loc_key.map(owned),
+      title_loc_args: self.title_loc_args,
+      mutable_content: self.mutable_content,
+      android_channel_id: self.android_channel_id.map(owned),
+      image: self.image.map(owned),
+      notification_priority: self.notification_priority,
+      default_sound: self.default_sound,
+      default_vibrate_timings: self.default_vibrate_timings,
+      vibrate_timings: self.vibrate_timings,
+      event_time: self.event_time.map(owned),
+      light_settings: self.light_settings,
+    }
+  }
+
+  /// Replace the title, e.g. to send variations of a notification.
+  /// # Examples:
+  /// ```rust
+  /// use gcm::NotificationBuilder;
+  ///
+  /// let score = NotificationBuilder::new("India vs. Australia")
+  ///     .body("3 runs to win in 1 ball")
+  ///     .finalize();
+  /// let final_score = score.clone().with_body("India won by 2 wickets");
+  ///
+  /// assert_eq!(score.title(), final_score.title());
+  /// assert_eq!(final_score.body(), Some("India won by 2 wickets"));
+  /// ```
+  pub fn with_title(mut self, title: impl Into<Cow<'a, str>>) -> Notification<'a> {
+    self.title = Some(title.into());
+    self
+  }
+
+  /// Replace the body. See `with_title`.
+  pub fn with_body(mut self, body: impl Into<Cow<'a, str>>) -> Notification<'a> {
+    self.body = Some(body.into());
+    self
+  }
+
+  /// Get a `NotificationBuilder` with the fields of this notification, to
+  /// change any of them and `finalize` again.
+  pub fn into_builder(self) -> NotificationBuilder<'a> {
+    NotificationBuilder {
+      title: self.title,
+      body: self.body,
+      icon: self.icon,
+      sound: self.sound,
+      badge: self.badge,
+      tag: self.tag,
+      color: self.color,
+      click_action: self.click_action,
+      body_loc_key: self.body_loc_key,
+      body_loc_args: self.body_loc_args,
+      title_loc_key: self.title_loc_key,
+      title_loc_args: self.title_loc_args,
+      mutable_content: self.mutable_content,
+      android_channel_id: self.android_channel_id,
+      image: self.image,
+      notification_priority: self.notification_priority,
+      default_sound: self.default_sound,
+      default_vibrate_timings: self.default_vibrate_timings,
+      vibrate_timings: self.vibrate_timings,
+      event_time: self.event_time,
+      light_settings: self.light_settings,
+    }
+  }
+}
+
+// Whether `color` is in #rgb or #rrggbb format.
+pub(crate) fn is_valid_color(color: &str) -> bool {
+  (color.len() == 4 || color.len() == 7)
+    && color.starts_with('#')
+    && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// Durations as FCM expects them: seconds, with a fraction if needed,
+// e.g. `3.5s`.
+fn format_duration(duration: Duration) -> String {
+  if duration.subsec_nanos() == 0 {
+    return format!("{}s", duration.as_secs());
+  }
+
+  let fraction = format!("{:09}", duration.subsec_nanos());
+  format!("{}.{}s", duration.as_secs(), fraction.trim_end_matches('0'))
+}
+
+// Whether `timestamp` is in RFC 3339 format, e.g. `2017-06-01T18:30:00Z`,
+// with optional fractional seconds and a `Z` or `+hh:mm` offset.
+fn is_rfc3339(timestamp: &str) -> bool {
+  let bytes = timestamp.as_bytes();
+  let number = |start: usize, end: usize| -> Option<u32> {
+    if end > bytes.len() || !bytes[start..end].iter().all(|byte| byte.is_ascii_digit()) {
+      return None;
+    }
+    timestamp[start..end].parse().ok()
+  };
+  let is = |at: usize, chars: &[u8]| bytes.get(at).map_or(false, |byte| chars.contains(byte));
+
+  let date_time_valid = number(0, 4).is_some()
+    && is(4, b"-") && number(5, 7).map_or(false, |month| (1..=12).contains(&month))
+    && is(7, b"-") && number(8, 10).map_or(false, |day| (1..=31).contains(&day))
+    && is(10, b"Tt") && number(11, 13).map_or(false, |hour| hour <= 23)
+    && is(13, b":") && number(14, 16).map_or(false, |minute| minute <= 59)
+    && is(16, b":") && number(17, 19).map_or(false, |second| second <= 60);
+
+  if !date_time_valid {
+    return false;
+  }
+
+  let mut offset = 19;
+  if is(offset, b".") {
+    let digits = bytes[offset + 1..].iter().take_while(|byte| byte.is_ascii_digit()).count();
+    if digits == 0 {
+      return false;
+    }
+    offset += 1 + digits;
+  }
+
+  match bytes.get(offset) {
+    Some(&b'Z') | Some(&b'z') => bytes.len() == offset + 1,
+    Some(&b'+') | Some(&b'-') => bytes.len() == offset + 6
+      && number(offset + 1, offset + 3).map_or(false, |hours| hours <= 23)
+      && is(offset + 3, b":")
+      && number(offset + 4, offset + 6).map_or(false, |minutes| minutes <= 59),
+    _ => false
+  }
+}
+
+fn owned(value: Cow<str>) -> Cow<'static, str> {
+  Cow::Owned(value.into_owned())
 }
 
 /// A builder to get a `Notification` instance.
@@ -41,28 +414,70 @@ pub struct Notification<'a> {
 ///     .body("3 runs to win in 1 ball")
 ///     .finalize();
 /// ```
+///
+/// The text fields take either a `&str` or an owned `String`:
+///
+/// ```rust
+/// use gcm::NotificationBuilder;
+///
+/// let runs = 3;
+/// let notification = NotificationBuilder::new("India vs. Australia")
+///     .body(format!("{} runs to win in 1 ball", runs))
+///     .finalize();
+/// ```
+///
+/// The `with_*` setters take the builder by value, to build it up over
+/// several statements:
+///
+/// ```rust
+/// use gcm::NotificationBuilder;
+///
+/// # let urgent = true;
+/// let builder = NotificationBuilder::new("India vs. Australia");
+/// let builder = builder.with_body("3 runs to win in 1 ball");
+/// let builder = if urgent { builder.with_sound("whistle") } else { builder };
+/// let notification = builder.finalize();
+/// ```
+#[derive(Clone)]
 pub struct NotificationBuilder<'a> {
-  title: &'a str,
-  body: Option<&'a str>,
-  icon: &'a str,
-  sound: Option<&'a str>,
-  badge: Option<&'a str>,
-  tag: Option<&'a str>,
-  color: Option<&'a str>,
-  click_action: Option<&'a str>,
-  body_loc_key: Option<&'a str>,
+  title: Option<Cow<'a, str>>,
+  body: Option<Cow<'a, str>>,
+  icon: Option<Cow<'a, str>>,
+  sound: Option<Cow<'a, str>>,
+  badge: Option<Cow<'a, str>>,
+  tag: Option<Cow<'a, str>>,
+  color: Option<Cow<'a, str>>,
+  click_action: Option<Cow<'a, str>>,
+  body_loc_key: Option<Cow<'a, str>>,
   body_loc_args: Option<Vec<String>>,
-  title_loc_key: Option<&'a str>,
+  title_loc_key: Option<Cow<'a, str>>,
   title_loc_args: Option<Vec<String>>,
+  mutable_content: Option<bool>,
+  android_channel_id: Option<Cow<'a, str>>,
+  image: Option<Cow<'a, str>>,
+  notification_priority: Option<NotificationPriority>,
+  default_sound: Option<bool>,
+  default_vibrate_timings: Option<bool>,
+  vibrate_timings: Option<Vec<String>>,
+  event_time: Option<Cow<'a, str>>,
+  light_settings: Option<LightSettings>,
 }
 
 impl <'a> NotificationBuilder<'a> {
   /// Get a new `NotificationBuilder` instance, with a title.
-  pub fn new(title: &'a str) -> NotificationBuilder<'a> {
+  pub fn new(title: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    let mut builder = NotificationBuilder::empty();
+    builder.title(title);
+    builder
+  }
+
+  /// Get a new `NotificationBuilder` instance without a title, e.g. for a
+  /// notification with only a body.
+  pub fn empty() -> NotificationBuilder<'a> {
     NotificationBuilder {
-      title: title,
+      title: None,
       body: None,
-      icon: "myicon",
+      icon: None,
       sound: None,
       badge: None,
       tag: None,
@@ -72,93 +487,332 @@ impl <'a> NotificationBuilder<'a> {
       body_loc_args: None,
       title_loc_key: None,
       title_loc_args: None,
+      mutable_content: None,
+      android_channel_id: None,
+      image: None,
+      notification_priority: None,
+      default_sound: None,
+      default_vibrate_timings: None,
+      vibrate_timings: None,
+      event_time: None,
+      light_settings: None,
     }
   }
 
+  /// Set the title of the notification
+  pub fn title(&mut self, title: impl Into<Cow<'a, str>>) -> &mut NotificationBuilder<'a> {
+    self.title = Some(title.into());
+    self
+  }
+
   /// Set the body of the notification
-  pub fn body(&mut self, body: &'a str) -> &mut NotificationBuilder<'a> {
-    self.body = Some(body);
+  pub fn body(&mut self, body: impl Into<Cow<'a, str>>) -> &mut NotificationBuilder<'a> {
+    self.body = Some(body.into());
     self
   }
 
-  /// Set the notification icon. Defaults to `myicon`
-  pub fn icon(&mut self, icon: &'a str) -> &mut NotificationBuilder<'a> {
-    self.icon = icon;
+  /// Set the notification icon, the name of a drawable in your app.
+  /// No icon is sent unless set.
+  pub fn icon(&mut self, icon: impl Into<Cow<'a, str>>) -> &mut NotificationBuilder<'a> {
+    self.icon = Some(icon.into());
     self
   }
 
   /// Set the sound to be played
-  pub fn sound(&mut self, sound: &'a str) -> &mut NotificationBuilder<'a> {
-    self.sound = Some(sound);
+  pub fn sound(&mut self, sound: impl Into<Cow<'a, str>>) -> &mut NotificationBuilder<'a> {
+    self.sound = Some(sound.into());
     self
   }
 
   /// Set the badge for iOS notifications
-  pub fn badge(&mut self, badge: &'a str) -> &mut NotificationBuilder<'a> {
-    self.badge = Some(badge);
+  pub fn badge(&mut self, badge: impl Into<Cow<'a, str>>) -> &mut NotificationBuilder<'a> {
+    self.badge = Some(badge.into());
     self
   }
 
   /// Tagging a notification allows you to replace existing notifications
   /// with the same tag with this new notification
-  pub fn tag(&mut self, tag: &'a str) -> &mut NotificationBuilder<'a> {
-    self.tag = Some(tag);
+  pub fn tag(&mut self, tag: impl Into<Cow<'a, str>>) -> &mut NotificationBuilder<'a> {
+    self.tag = Some(tag.into());
     self
   }
 
-  /// The color of the icon, in #rrggbb format
-  pub fn color(&mut self, color: &'a str) -> &mut NotificationBuilder<'a> {
-    self.color = Some(color);
+  /// The color of the icon, in #rgb or #rrggbb format. Malformed colors fail
+  /// `Message::validate`; use `try_color` to check it right away.
+  pub fn color(&mut self, color: impl Into<Cow<'a, str>>) -> &mut NotificationBuilder<'a> {
+    self.color = Some(color.into());
     self
   }
 
+  /// Like `color`, but returns `InvalidMessage` unless the color is in
+  /// #rgb or #rrggbb format.
+  pub fn try_color(&mut self, color: impl Into<Cow<'a, str>>) -> Result<&mut NotificationBuilder<'a>, GcmError> {
+    let color = color.into();
+
+    if !is_valid_color(&color) {
+      return Err(GcmError::InvalidMessage(format!("invalid color `{}`, expected #rgb or #rrggbb", color)));
+    }
+
+    Ok(self.color(color))
+  }
+
   /// What happens when the user clicks on the notification. Refer to 
   /// https://developers.google.com/cloud-messaging/http-server-ref#table2 for
   /// details.
-  pub fn click_action(&mut self, click_action: &'a str) -> &mut NotificationBuilder<'a> {
-    self.click_action = Some(click_action);
+  pub fn click_action(&mut self, click_action: impl Into<Cow<'a, str>>) -> &mut NotificationBuilder<'a> {
+    self.click_action = Some(click_action.into());
     self
   }
 
   /// Set the body key string for localization
-  pub fn body_loc_key(&mut self, body_loc_key: &'a str) -> &mut NotificationBuilder<'a> {
-    self.body_loc_key = Some(body_loc_key);
+  pub fn body_loc_key(&mut self, body_loc_key: impl Into<Cow<'a, str>>) -> &mut NotificationBuilder<'a> {
+    self.body_loc_key = Some(body_loc_key.into());
     self
   }
 
-  /// String value to replace format specifiers in the body string.
-  pub fn body_loc_args(&mut self, body_loc_args: Vec<&'a str>) -> &mut NotificationBuilder<'a> {
-    self.body_loc_args = Some(body_loc_args.iter().map(|s| s.to_string()).collect());
+  /// String value to replace format specifiers in the body string, as
+  /// `&str`s or `String`s.
+  pub fn body_loc_args(&mut self, body_loc_args: Vec<impl Into<String>>) -> &mut NotificationBuilder<'a> {
+    self.body_loc_args = Some(body_loc_args.into_iter().map(Into::into).collect());
     self
   }
 
   /// Set the title key string for localization
-  pub fn title_loc_key(&mut self, title_loc_key: &'a str) -> &mut NotificationBuilder<'a> {
-    self.title_loc_key = Some(title_loc_key);
+  pub fn title_loc_key(&mut self, title_loc_key: impl Into<Cow<'a, str>>) -> &mut NotificationBuilder<'a> {
+    self.title_loc_key = Some(title_loc_key.into());
+    self
+  }
+
+  /// String value to replace format specifiers in the title string, as
+  /// `&str`s or `String`s.
+  pub fn title_loc_args(&mut self, title_loc_args: Vec<impl Into<String>>) -> &mut NotificationBuilder<'a> {
+    self.title_loc_args = Some(title_loc_args.into_iter().map(Into::into).collect());
     self
   }
 
-  /// String value to replace format specifiers in the title string.
-  pub fn title_loc_args(&mut self, title_loc_args: Vec<&'a str>) -> &mut NotificationBuilder<'a> {
-    self.title_loc_args = Some(title_loc_args.iter().map(|s| s.to_string()).collect());
+  /// Set `mutable-content` on iOS, so that a notification service
+  /// extension can modify the notification before it is displayed
+  pub fn mutable_content(&mut self, mutable_content: bool) -> &mut NotificationBuilder<'a> {
+    self.mutable_content = Some(mutable_content);
     self
   }
 
-  /// Complete the build and get a `Notification` instance
-  pub fn finalize(&mut self) -> Notification<'a> {
+  /// Set the notification channel on Android 8.0 and above. Notifications
+  /// without a channel are not displayed on those versions
+  pub fn android_channel_id(&mut self, android_channel_id: impl Into<Cow<'a, str>>) -> &mut NotificationBuilder<'a> {
+    self.android_channel_id = Some(android_channel_id.into());
+    self
+  }
+
+  /// Set the URL of an image to be shown in a big picture style notification
+  pub fn image(&mut self, image: impl Into<Cow<'a, str>>) -> &mut NotificationBuilder<'a> {
+    self.image = Some(image.into());
+    self
+  }
+
+  /// Set the relative priority of the notification on Android, which
+  /// decides, among other things, whether it is shown as a heads-up
+  pub fn notification_priority(&mut self, notification_priority: NotificationPriority) -> &mut NotificationBuilder<'a> {
+    self.notification_priority = Some(notification_priority);
+    self
+  }
+
+  /// Use the Android framework's default sound, ignoring `sound`
+  pub fn default_sound(&mut self, default_sound: bool) -> &mut NotificationBuilder<'a> {
+    self.default_sound = Some(default_sound);
+    self
+  }
+
+  /// Use the Android framework's default vibration pattern, ignoring
+  /// `vibrate_timings`
+  pub fn default_vibrate_timings(&mut self, default_vibrate_timings: bool) -> &mut NotificationBuilder<'a> {
+    self.default_vibrate_timings = Some(default_vibrate_timings);
+    self
+  }
+
+  /// Set the vibration pattern: how long to vibrate, then pause, then
+  /// vibrate and so on. Sent as durations like `"3.5s"`
+  pub fn vibrate_timings(&mut self, vibrate_timings: Vec<Duration>) -> &mut NotificationBuilder<'a> {
+    self.vibrate_timings = Some(vibrate_timings.into_iter().map(format_duration).collect());
+    self
+  }
+
+  /// Set the time the event in the notification happened, shown as the
+  /// notification time. Returns `InvalidMessage` unless it is an RFC 3339
+  /// timestamp, like `2017-06-01T18:30:00Z`.
+  pub fn event_time(&mut self, event_time: impl Into<Cow<'a, str>>) -> Result<&mut NotificationBuilder<'a>, GcmError> {
+    let event_time = event_time.into();
+
+    if !is_rfc3339(&event_time) {
+      return Err(GcmError::InvalidMessage(format!("invalid event_time `{}`, expected an RFC 3339 timestamp", event_time)));
+    }
+
+    self.event_time = Some(event_time);
+    Ok(self)
+  }
+
+  /// Set the color and blink rate of the notification LED, if the device
+  /// has one.
+  pub fn light_settings(&mut self, light_settings: LightSettings) -> &mut NotificationBuilder<'a> {
+    self.light_settings = Some(light_settings);
+    self
+  }
+
+  /// Complete the build and get a `Notification` instance. The builder is
+  /// left as it is, so it can serve as a template for more notifications.
+  pub fn finalize(&self) -> Notification<'a> {
     Notification {
-      title: self.title,
-      body: self.body,
-      icon: self.icon,
-      sound: self.sound,
-      badge: self.badge,
-      tag: self.tag,
-      color: self.color,
-      click_action: self.click_action,
-      body_loc_key: self.body_loc_key,
+      title: self.title.clone(),
+      body: self.body.clone(),
+      icon: self.icon.clone(),
+      sound: self.sound.clone(),
+      badge: self.badge.clone(),
+      tag: self.tag.clone(),
+      color: self.color.clone(),
+      click_action: self.click_action.clone(),
+      body_loc_key: self.body_loc_key.clone(),
       body_loc_args: self.body_loc_args.clone(),
-      title_loc_key: self.title_loc_key,
+      title_loc_key: self.title_loc_key.clone(),
       title_loc_args: self.title_loc_args.clone(),
+      mutable_content: self.mutable_content,
+      android_channel_id: self.android_channel_id.clone(),
+      image: self.image.clone(),
+      notification_priority: self.notification_priority,
+      default_sound: self.default_sound,
+      default_vibrate_timings: self.default_vibrate_timings,
+      vibrate_timings: self.vibrate_timings.clone(),
+      event_time: self.event_time.clone(),
+      light_settings: self.light_settings.clone(),
     }
   }
-}
\ No newline at end of file
+}
+
+// The same setters, taking and returning the builder by value, so that it
+// can be kept in a variable and built up over several statements.
+impl <'a> NotificationBuilder<'a> {
+  /// Like `title`, but consumes and returns the builder.
+  pub fn with_title(mut self, title: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    self.title(title);
+    self
+  }
+
+  /// Like `body`, but consumes and returns the builder.
+  pub fn with_body(mut self, body: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    self.body(body);
+    self
+  }
+
+  /// Like `icon`, but consumes and returns the builder.
+  pub fn with_icon(mut self, icon: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    self.icon(icon);
+    self
+  }
+
+  /// Like `sound`, but consumes and returns the builder.
+  pub fn with_sound(mut self, sound: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    self.sound(sound);
+    self
+  }
+
+  /// Like `badge`, but consumes and returns the builder.
+  pub fn with_badge(mut self, badge: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    self.badge(badge);
+    self
+  }
+
+  /// Like `tag`, but consumes and returns the builder.
+  pub fn with_tag(mut self, tag: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    self.tag(tag);
+    self
+  }
+
+  /// Like `color`, but consumes and returns the builder.
+  pub fn with_color(mut self, color: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    self.color(color);
+    self
+  }
+
+  /// Like `click_action`, but consumes and returns the builder.
+  pub fn with_click_action(mut self, click_action: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    self.click_action(click_action);
+    self
+  }
+
+  /// Like `body_loc_key`, but consumes and returns the builder.
+  pub fn with_body_loc_key(mut self, body_loc_key: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    self.body_loc_key(body_loc_key);
+    self
+  }
+
+  /// Like `body_loc_args`, but consumes and returns the builder.
+  pub fn with_body_loc_args(mut self, body_loc_args: Vec<impl Into<String>>) -> NotificationBuilder<'a> {
+    self.body_loc_args(body_loc_args);
+    self
+  }
+
+  /// Like `title_loc_key`, but consumes and returns the builder.
+  pub fn with_title_loc_key(mut self, title_loc_key: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    self.title_loc_key(title_loc_key);
+    self
+  }
+
+  /// Like `title_loc_args`, but consumes and returns the builder.
+  pub fn with_title_loc_args(mut self, title_loc_args: Vec<impl Into<String>>) -> NotificationBuilder<'a> {
+    self.title_loc_args(title_loc_args);
+    self
+  }
+
+  /// Like `mutable_content`, but consumes and returns the builder.
+  pub fn with_mutable_content(mut self, mutable_content: bool) -> NotificationBuilder<'a> {
+    self.mutable_content(mutable_content);
+    self
+  }
+
+  /// Like `android_channel_id`, but consumes and returns the builder.
+  pub fn with_android_channel_id(mut self, android_channel_id: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    self.android_channel_id(android_channel_id);
+    self
+  }
+
+  /// Like `image`, but consumes and returns the builder.
+  pub fn with_image(mut self, image: impl Into<Cow<'a, str>>) -> NotificationBuilder<'a> {
+    self.image(image);
+    self
+  }
+
+  /// Like `notification_priority`, but consumes and returns the builder.
+  pub fn with_notification_priority(mut self, notification_priority: NotificationPriority) -> NotificationBuilder<'a> {
+    self.notification_priority(notification_priority);
+    self
+  }
+
+  /// Like `default_sound`, but consumes and returns the builder.
+  pub fn with_default_sound(mut self, default_sound: bool) -> NotificationBuilder<'a> {
+    self.default_sound(default_sound);
+    self
+  }
+
+  /// Like `default_vibrate_timings`, but consumes and returns the builder.
+  pub fn with_default_vibrate_timings(mut self, default_vibrate_timings: bool) -> NotificationBuilder<'a> {
+    self.default_vibrate_timings(default_vibrate_timings);
+    self
+  }
+
+  /// Like `vibrate_timings`, but consumes and returns the builder.
+  pub fn with_vibrate_timings(mut self, vibrate_timings: Vec<Duration>) -> NotificationBuilder<'a> {
+    self.vibrate_timings(vibrate_timings);
+    self
+  }
+
+  /// Like `light_settings`, but consumes and returns the builder.
+  pub fn with_light_settings(mut self, light_settings: LightSettings) -> NotificationBuilder<'a> {
+    self.light_settings(light_settings);
+    self
+  }
+
+  /// Like `event_time`, but consumes and returns the builder.
+  pub fn with_event_time(mut self, event_time: impl Into<Cow<'a, str>>) -> Result<NotificationBuilder<'a>, GcmError> {
+    self.event_time(event_time)?;
+    Ok(self)
+  }
+}
@@ -1,11 +1,59 @@
 use serde_json;
-use {NotificationBuilder};
+use std::collections::HashSet;
+use std::time::Duration;
+use {GcmError, LightSettings, NotificationBuilder, NotificationPriority};
 
 #[test]
 fn should_create_new_notification_message() {
   let nm = NotificationBuilder::new("title").finalize();
 
-  assert_eq!(nm.title, "title");
+  assert_eq!(nm.title, Some("title".into()));
+}
+
+#[test]
+fn should_read_back_notification_fields() {
+  let nm = NotificationBuilder::new("title").finalize();
+
+  assert_eq!(nm.title(), Some("title"));
+  assert_eq!(nm.body(), None);
+  assert_eq!(nm.icon(), None);
+  assert_eq!(nm.sound(), None);
+  assert_eq!(nm.notification_priority(), None);
+
+  let nm = NotificationBuilder::new("title")
+      .body("body")
+      .icon("icon")
+      .sound("sound.wav")
+      .badge("1")
+      .tag("tag")
+      .color("#ffffff")
+      .click_action("action")
+      .body_loc_key("body_key")
+      .body_loc_args(vec!["body_arg"])
+      .title_loc_key("title_key")
+      .title_loc_args(vec!["title_arg"])
+      .mutable_content(true)
+      .android_channel_id("updates")
+      .image("https://example.com/score.png")
+      .notification_priority(NotificationPriority::High)
+      .finalize();
+
+  assert_eq!(nm.title(), Some("title"));
+  assert_eq!(nm.body(), Some("body"));
+  assert_eq!(nm.icon(), Some("icon"));
+  assert_eq!(nm.sound(), Some("sound.wav"));
+  assert_eq!(nm.badge(), Some("1"));
+  assert_eq!(nm.tag(), Some("tag"));
+  assert_eq!(nm.color(), Some("#ffffff"));
+  assert_eq!(nm.click_action(), Some("action"));
+  assert_eq!(nm.body_loc_key(), Some("body_key"));
+  assert_eq!(nm.body_loc_args(), Some(&["body_arg".to_string()][..]));
+  assert_eq!(nm.title_loc_key(), Some("title_key"));
+  assert_eq!(nm.title_loc_args(), Some(&["title_arg".to_string()][..]));
+  assert_eq!(nm.mutable_content(), Some(true));
+  assert_eq!(nm.android_channel_id(), Some("updates"));
+  assert_eq!(nm.image(), Some("https://example.com/score.png"));
+  assert_eq!(nm.notification_priority(), Some(NotificationPriority::High));
 }
 
 #[test]
@@ -20,16 +68,17 @@ fn should_set_notification_body() {
 
   let json_result = serde_json::to_string(&nm);
 
-  assert_eq!(nm.body, Some("body"));
+  assert_eq!(nm.body, Some("body".into()));
   assert!(json_result.is_ok());
-  assert_eq!(json_result.unwrap(), r#"{"title":"title","body":"body","icon":"myicon"}"#);
+  assert_eq!(json_result.unwrap(), r#"{"title":"title","body":"body"}"#);
 }
 
 #[test]
-fn should_set_default_icon() {
+fn should_not_set_icon_by_default() {
   let nm = NotificationBuilder::new("title").finalize();
 
-  assert_eq!(nm.icon, "myicon");
+  assert_eq!(nm.icon, None);
+  assert_eq!(serde_json::to_string(&nm).unwrap(), r#"{"title":"title"}"#);
 }
 
 #[test]
@@ -38,7 +87,8 @@ fn should_set_notification_icon() {
       .icon("newicon")
       .finalize();
 
-  assert_eq!(nm.icon, "newicon");
+  assert_eq!(nm.icon, Some("newicon".into()));
+  assert_eq!(serde_json::to_string(&nm).unwrap(), r#"{"title":"title","icon":"newicon"}"#);
 }
 
 #[test]
@@ -51,7 +101,7 @@ fn should_set_notification_sound() {
       .sound("sound.wav")
       .finalize();
 
-  assert_eq!(nm.sound, Some("sound.wav"));
+  assert_eq!(nm.sound, Some("sound.wav".into()));
 }
 
 #[test]
@@ -64,7 +114,7 @@ fn should_set_notification_badge() {
       .badge("1")
       .finalize();
 
-  assert_eq!(nm.badge, Some("1"));
+  assert_eq!(nm.badge, Some("1".into()));
 }
 
 #[test]
@@ -77,7 +127,7 @@ fn should_set_notification_tag() {
       .tag("tag")
       .finalize();
 
-  assert_eq!(nm.tag, Some("tag"));
+  assert_eq!(nm.tag, Some("tag".into()));
 }
 
 #[test]
@@ -90,7 +140,7 @@ fn should_set_notification_color() {
       .color("color")
       .finalize();
 
-  assert_eq!(nm.color, Some("color"));
+  assert_eq!(nm.color, Some("color".into()));
 }
 
 #[test]
@@ -103,7 +153,7 @@ fn should_set_notification_click_action() {
       .click_action("action")
       .finalize();
 
-  assert_eq!(nm.click_action, Some("action"));
+  assert_eq!(nm.click_action, Some("action".into()));
 }
 
 #[test]
@@ -116,7 +166,7 @@ fn should_set_notification_body_loc_key() {
       .body_loc_key("key")
       .finalize();
 
-  assert_eq!(nm.body_loc_key, Some("key"));
+  assert_eq!(nm.body_loc_key, Some("key".into()));
 }
 
 #[test]
@@ -132,7 +182,21 @@ fn should_set_notification_body_loc_args() {
   let json_result = serde_json::to_string(&nm);
 
   assert_eq!(nm.body_loc_args, Some(vec!["args".to_string()]));
-  assert_eq!(json_result.unwrap(), r#"{"title":"title","icon":"myicon","body_loc_args":["args"]}"#);
+  assert_eq!(json_result.unwrap(), r#"{"title":"title","body_loc_args":["args"]}"#);
+}
+
+#[test]
+fn should_set_notification_loc_args_from_owned_strings() {
+  let runs = 3;
+  let args = vec![runs.to_string(), "1 ball".to_string()];
+
+  let nm = NotificationBuilder::new("title")
+      .body_loc_args(args.clone())
+      .title_loc_args(vec![format!("{} runs", runs)])
+      .finalize();
+
+  assert_eq!(nm.body_loc_args(), Some(&args[..]));
+  assert_eq!(nm.title_loc_args(), Some(&["3 runs".to_string()][..]));
 }
 
 #[test]
@@ -145,7 +209,7 @@ fn should_set_notification_title_loc_key() {
       .title_loc_key("key")
       .finalize();
 
-  assert_eq!(nm.title_loc_key, Some("key"));
+  assert_eq!(nm.title_loc_key, Some("key".into()));
 }
 
 #[test]
@@ -161,5 +225,340 @@ fn should_set_notification_title_loc_args() {
   let json_result = serde_json::to_string(&nm);
 
   assert_eq!(nm.title_loc_args, Some(vec!["args".to_string()]));
-  assert_eq!(json_result.unwrap(), r#"{"title":"title","icon":"myicon","title_loc_args":["args"]}"#);
-}
\ No newline at end of file
+  assert_eq!(json_result.unwrap(), r#"{"title":"title","title_loc_args":["args"]}"#);
+}
+
+#[test]
+fn should_set_notification_mutable_content() {
+  let nm = NotificationBuilder::new("title").finalize();
+
+  assert_eq!(nm.mutable_content, None);
+  assert_eq!(serde_json::to_string(&nm).unwrap(), r#"{"title":"title"}"#);
+
+  let nm = NotificationBuilder::new("title")
+      .mutable_content(true)
+      .finalize();
+
+  let json_result = serde_json::to_string(&nm);
+
+  assert_eq!(nm.mutable_content, Some(true));
+  assert_eq!(json_result.unwrap(), r#"{"title":"title","mutable_content":true}"#);
+}
+
+#[test]
+fn should_set_notification_android_channel_id() {
+  let nm = NotificationBuilder::new("title").finalize();
+
+  assert_eq!(nm.android_channel_id, None);
+
+  let nm = NotificationBuilder::new("title")
+      .android_channel_id("updates")
+      .finalize();
+
+  let json_result = serde_json::to_string(&nm);
+
+  assert_eq!(nm.android_channel_id, Some("updates".into()));
+  assert_eq!(json_result.unwrap(), r#"{"title":"title","android_channel_id":"updates"}"#);
+}
+
+#[test]
+fn should_set_notification_image() {
+  let nm = NotificationBuilder::new("title").finalize();
+
+  assert_eq!(nm.image, None);
+
+  let nm = NotificationBuilder::new("title")
+      .image("https://example.com/score.png")
+      .finalize();
+
+  let json_result = serde_json::to_string(&nm);
+
+  assert_eq!(nm.image, Some("https://example.com/score.png".into()));
+  assert_eq!(json_result.unwrap(),
+    r#"{"title":"title","image":"https://example.com/score.png"}"#);
+}
+
+#[test]
+fn should_set_notification_priority() {
+  let nm = NotificationBuilder::new("title").finalize();
+
+  assert_eq!(nm.notification_priority, None);
+
+  let priorities = vec![
+    (NotificationPriority::Min, "PRIORITY_MIN"),
+    (NotificationPriority::Low, "PRIORITY_LOW"),
+    (NotificationPriority::Default, "PRIORITY_DEFAULT"),
+    (NotificationPriority::High, "PRIORITY_HIGH"),
+    (NotificationPriority::Max, "PRIORITY_MAX"),
+  ];
+
+  for (priority, expected) in priorities {
+    let nm = NotificationBuilder::new("title")
+        .notification_priority(priority)
+        .finalize();
+
+    let json_result = serde_json::to_string(&nm);
+
+    assert_eq!(nm.notification_priority, Some(priority));
+    assert_eq!(json_result.unwrap(),
+      format!(r#"{{"title":"title","notification_priority":"{}"}}"#, expected));
+  }
+}
+
+#[test]
+fn should_accept_valid_colors() {
+  for color in &["#fff", "#FFF", "#a1b2c3", "#00FF7f"] {
+    let nm = NotificationBuilder::new("title").try_color(*color).unwrap().finalize();
+
+    assert_eq!(nm.color(), Some(*color));
+  }
+}
+
+#[test]
+fn should_reject_invalid_colors() {
+  for color in &["fff", "#ff", "#ffff", "#ggg", "#1234567", "red", ""] {
+    let result = NotificationBuilder::new("title").try_color(*color).map(|builder| builder.finalize());
+
+    assert_eq!(result.err().unwrap(),
+      GcmError::InvalidMessage(format!("invalid color `{}`, expected #rgb or #rrggbb", color)));
+  }
+}
+
+#[test]
+fn should_accept_owned_strings() {
+  let runs = 3;
+  let title = String::from("India vs. Australia");
+
+  let nm = NotificationBuilder::new(title)
+    .body(format!("{} runs to win in 1 ball", runs))
+    .tag("score")
+    .finalize();
+
+  assert_eq!(nm.title(), Some("India vs. Australia"));
+  assert_eq!(nm.body(), Some("3 runs to win in 1 ball"));
+  assert_eq!(nm.tag(), Some("score"));
+}
+
+#[test]
+fn should_create_notification_without_title() {
+  let nm = NotificationBuilder::empty()
+    .body("3 runs to win in 1 ball")
+    .finalize();
+
+  assert_eq!(nm.title(), None);
+  assert_eq!(serde_json::to_string(&nm).unwrap(), r#"{"body":"3 runs to win in 1 ball"}"#);
+}
+
+#[test]
+fn should_set_title_on_empty_notification() {
+  let nm = NotificationBuilder::empty()
+    .title("title")
+    .finalize();
+
+  assert_eq!(nm, NotificationBuilder::new("title").finalize());
+}
+
+#[test]
+fn should_finalize_builder_more_than_once() {
+  let mut builder = NotificationBuilder::new("India vs. Australia");
+  builder.icon("ball");
+
+  let first = builder.finalize();
+  let second = builder.finalize();
+
+  assert_eq!(first, second);
+
+  let mut edited = builder.clone();
+  edited.body("Australia won");
+
+  assert_eq!(edited.finalize().body(), Some("Australia won"));
+  assert_eq!(builder.finalize().body(), None);
+}
+
+#[test]
+fn should_set_notification_default_sound() {
+  let nm = NotificationBuilder::new("title").finalize();
+
+  assert_eq!(nm.default_sound, None);
+
+  let nm = NotificationBuilder::new("title")
+      .default_sound(true)
+      .finalize();
+
+  assert_eq!(nm.default_sound(), Some(true));
+  assert_eq!(serde_json::to_string(&nm).unwrap(), r#"{"title":"title","default_sound":true}"#);
+}
+
+#[test]
+fn should_set_notification_default_vibrate_timings() {
+  let nm = NotificationBuilder::new("title").finalize();
+
+  assert_eq!(nm.default_vibrate_timings, None);
+
+  let nm = NotificationBuilder::new("title")
+      .default_vibrate_timings(true)
+      .finalize();
+
+  assert_eq!(nm.default_vibrate_timings(), Some(true));
+}
+
+#[test]
+fn should_set_notification_vibrate_timings() {
+  let nm = NotificationBuilder::new("title").finalize();
+
+  assert_eq!(nm.vibrate_timings, None);
+
+  let nm = NotificationBuilder::new("title")
+      .vibrate_timings(vec![Duration::from_millis(3500), Duration::from_secs(1), Duration::from_millis(250)])
+      .finalize();
+
+  assert_eq!(serde_json::to_string(&nm).unwrap(),
+    r#"{"title":"title","vibrate_timings":["3.5s","1s","0.25s"]}"#);
+}
+
+#[test]
+fn should_set_notification_event_time() {
+  let nm = NotificationBuilder::new("title").finalize();
+
+  assert_eq!(nm.event_time, None);
+
+  for event_time in &["2017-06-01T18:30:00Z", "2017-06-01t18:30:00.123z", "2017-06-01T18:30:00+05:30", "2016-12-31T23:59:60-08:00"] {
+    let nm = NotificationBuilder::new("title")
+        .event_time(*event_time).unwrap()
+        .finalize();
+
+    assert_eq!(nm.event_time(), Some(*event_time));
+  }
+}
+
+#[test]
+fn should_reject_invalid_event_time() {
+  for event_time in &["", "2017-06-01", "2017-06-01 18:30:00Z", "2017-06-01T18:30:00", "2017-13-01T18:30:00Z",
+                      "2017-06-01T24:00:00Z", "2017-06-01T18:30:00.Z", "2017-06-01T18:30:00+0530"] {
+    let result = NotificationBuilder::new("title").event_time(*event_time).map(|builder| builder.finalize());
+
+    assert_eq!(result.err().unwrap(), GcmError::InvalidMessage(
+      format!("invalid event_time `{}`, expected an RFC 3339 timestamp", event_time)));
+  }
+}
+
+#[test]
+fn should_set_notification_light_settings() {
+  let nm = NotificationBuilder::new("title").finalize();
+
+  assert_eq!(nm.light_settings, None);
+
+  let light_settings = LightSettings::new("#ff0000", Duration::from_millis(3500), Duration::from_secs(1)).unwrap();
+  let nm = NotificationBuilder::new("title")
+      .light_settings(light_settings.clone())
+      .finalize();
+
+  assert_eq!(nm.light_settings(), Some(&light_settings));
+  assert_eq!(serde_json::to_value(&nm).unwrap()["light_settings"], json!({
+    "color": { "red": 1.0, "green": 0.0, "blue": 0.0, "alpha": 1.0 },
+    "light_on_duration": "3.5s",
+    "light_off_duration": "1s"
+  }));
+}
+
+#[test]
+fn should_round_trip_light_settings() {
+  let light_settings = LightSettings::new("#0a8", Duration::from_secs(1), Duration::from_secs(2)).unwrap();
+
+  let json = serde_json::to_string(&light_settings).unwrap();
+
+  assert_eq!(serde_json::from_str::<LightSettings>(&json).unwrap(), light_settings);
+}
+
+#[test]
+fn should_reject_invalid_light_color() {
+  let result = LightSettings::new("red", Duration::from_secs(1), Duration::from_secs(1));
+
+  assert_eq!(result.err().unwrap(), GcmError::InvalidMessage("invalid color `red`, expected #rgb or #rrggbb".to_string()));
+}
+
+#[test]
+fn should_chain_owned_builder_across_statements() {
+  let builder = NotificationBuilder::new("title");
+  let builder = builder.with_body("body");
+  let builder = builder.with_tag("tag").with_badge("1");
+  let builder = builder.with_event_time("2017-06-01T18:30:00Z").unwrap();
+  let nm = builder.finalize();
+
+  assert_eq!(nm.title(), Some("title"));
+  assert_eq!(nm.body(), Some("body"));
+  assert_eq!(nm.tag(), Some("tag"));
+  assert_eq!(nm.badge(), Some("1"));
+  assert_eq!(nm.event_time(), Some("2017-06-01T18:30:00Z"));
+}
+
+#[test]
+fn should_build_the_same_notification_either_way() {
+  let mut borrowed = NotificationBuilder::new("title");
+  borrowed.body("body").sound("sound").mutable_content(true);
+
+  let owned = NotificationBuilder::new("title")
+    .with_body("body")
+    .with_sound("sound")
+    .with_mutable_content(true);
+
+  assert_eq!(owned.finalize(), borrowed.finalize());
+}
+
+#[test]
+fn should_reject_invalid_event_time_on_owned_builder() {
+  let result = NotificationBuilder::new("title").with_event_time("yesterday");
+
+  assert!(result.is_err());
+}
+
+#[test]
+fn should_adjust_finalized_notification() {
+  let notification = NotificationBuilder::new("title")
+      .body("body")
+      .tag("tag")
+      .finalize();
+
+  let variant = notification.clone()
+      .with_title("other title")
+      .with_body("other body");
+
+  assert_eq!(variant.title(), Some("other title"));
+  assert_eq!(variant.body(), Some("other body"));
+  assert_eq!(variant.tag(), Some("tag"));
+  assert_eq!(notification.body(), Some("body"));
+}
+
+#[test]
+fn should_turn_notification_back_into_builder() {
+  let notification = NotificationBuilder::new("title")
+      .body("body")
+      .notification_priority(NotificationPriority::High)
+      .finalize();
+
+  let variant = notification.clone().into_builder()
+      .with_sound("whistle")
+      .finalize();
+
+  assert_eq!(variant.sound(), Some("whistle"));
+  assert_eq!(variant.notification_priority(), Some(NotificationPriority::High));
+  assert_eq!(notification.clone().into_builder().finalize(), notification);
+}
+
+#[test]
+fn should_dedupe_notifications_in_a_set() {
+  let light_settings = LightSettings::new("#ff0000", Duration::from_millis(3500), Duration::from_secs(1)).unwrap();
+  let notification = |title: &'static str| NotificationBuilder::new(title)
+      .body("body")
+      .notification_priority(NotificationPriority::High)
+      .light_settings(light_settings.clone())
+      .finalize();
+
+  let mut notifications = HashSet::new();
+  notifications.insert(notification("title"));
+  notifications.insert(notification("title"));
+  notifications.insert(notification("other title"));
+
+  assert_eq!(notifications.len(), 2);
+  assert!(notifications.contains(&notification("title")));
+}
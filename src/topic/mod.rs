@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod tests;
+
+use GcmError;
+use std::fmt::{self, Display};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Operator {
+  And,
+  Or
+}
+
+/// A condition over topics, to send a message to every device matching it.
+/// Combine topics with `and` and `or`; parentheses are added as needed.
+/// The rendered condition is what `Message::condition` expects.
+///
+/// # Examples
+///
+/// ```rust
+/// use gcm::Topic;
+///
+/// let condition = Topic::new("India").unwrap()
+///     .and(Topic::new("Cricket").unwrap().or(Topic::new("Hockey").unwrap()));
+///
+/// assert_eq!(condition.to_string(),
+///     "'India' in topics && ('Cricket' in topics || 'Hockey' in topics)");
+/// ```
+#[derive(PartialEq, Debug, Clone)]
+pub struct Topic {
+  condition: String,
+  operator: Option<Operator>,
+}
+
+impl Topic {
+  /// A condition matching devices subscribed to the named topic. Returns
+  /// `InvalidMessage` unless the name matches `[a-zA-Z0-9-_.~%]+`, so that
+  /// it can't break out of its quotes.
+  pub fn new(name: &str) -> Result<Topic, GcmError> {
+    if !is_valid_name(name) {
+      return Err(GcmError::InvalidMessage(format!("invalid topic name `{}`", name)));
+    }
+
+    Ok(Topic {
+      condition: format!("'{}' in topics", name),
+      operator: None,
+    })
+  }
+
+  /// Matches devices that satisfy both conditions.
+  pub fn and(self, other: Topic) -> Topic {
+    Topic::combine(self, other, Operator::And)
+  }
+
+  /// Matches devices that satisfy either condition.
+  pub fn or(self, other: Topic) -> Topic {
+    Topic::combine(self, other, Operator::Or)
+  }
+
+  fn combine(left: Topic, right: Topic, operator: Operator) -> Topic {
+    let symbol = match operator {
+      Operator::And => "&&",
+      Operator::Or => "||"
+    };
+
+    Topic {
+      condition: format!("{} {} {}", left.operand(operator), symbol, right.operand(operator)),
+      operator: Some(operator),
+    }
+  }
+
+  // Parenthesize compound conditions using a different operator, so that
+  // precedence is explicit.
+  fn operand(&self, operator: Operator) -> String {
+    match self.operator {
+      Some(own) if own != operator => format!("({})", self.condition),
+      _ => self.condition.clone()
+    }
+  }
+}
+
+// Whether `name` is a valid topic name, i.e. matches `[a-zA-Z0-9-_.~%]+`.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+  !name.is_empty() && name.chars()
+    .all(|c| c.is_ascii_alphanumeric() || "-_.~%".contains(c))
+}
+
+impl Display for Topic {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.condition)
+  }
+}
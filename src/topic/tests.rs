@@ -0,0 +1,44 @@
+use {GcmError, Topic};
+
+#[test]
+fn should_render_single_topic() {
+  assert_eq!(Topic::new("TopicA").unwrap().to_string(), "'TopicA' in topics");
+}
+
+#[test]
+fn should_render_and_condition() {
+  let condition = Topic::new("TopicA").unwrap().and(Topic::new("TopicB").unwrap());
+
+  assert_eq!(condition.to_string(), "'TopicA' in topics && 'TopicB' in topics");
+}
+
+#[test]
+fn should_not_parenthesize_same_operator() {
+  let condition = Topic::new("A").unwrap()
+      .or(Topic::new("B").unwrap())
+      .or(Topic::new("C").unwrap());
+
+  assert_eq!(condition.to_string(), "'A' in topics || 'B' in topics || 'C' in topics");
+}
+
+#[test]
+fn should_parenthesize_mixed_operators() {
+  let condition = Topic::new("A").unwrap()
+      .or(Topic::new("B").unwrap())
+      .and(Topic::new("C").unwrap());
+
+  assert_eq!(condition.to_string(), "('A' in topics || 'B' in topics) && 'C' in topics");
+}
+
+#[test]
+fn should_reject_invalid_topic_names() {
+  for name in &["", "news' in topics || 'sports", "a b", "café"] {
+    assert_eq!(Topic::new(name).err().unwrap(),
+      GcmError::InvalidMessage(format!("invalid topic name `{}`", name)));
+  }
+}
+
+#[test]
+fn should_accept_valid_topic_names() {
+  assert!(Topic::new("news-2017_v1.0~%20").is_ok());
+}
@@ -0,0 +1,504 @@
+#[cfg(test)]
+mod tests;
+#[cfg(feature = "reqwest")]
+mod reqwest_transport;
+
+#[cfg(feature = "reqwest")]
+pub use self::reqwest_transport::ReqwestTransport;
+
+use std::error;
+use std::fmt::{self, Display};
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::Client;
+use hyper::client::pool::{Config, Pool};
+use hyper::header::Headers;
+use hyper::method::Method;
+use hyper::net::HttpsConnector;
+use hyper_native_tls::NativeTlsClient;
+use native_tls::{Certificate, TlsConnector};
+
+/// The HTTP layer used by `GcmClient` to talk to GCM. The default is
+/// `HyperTransport`; swap in a `MockTransport` to test code that sends
+/// messages without hitting Google's servers. Transports are shared between
+/// threads by `GcmClient::send_multicast`, so they must be `Send + Sync`.
+pub trait Transport: Send + Sync {
+  /// POST a body to `url`, with the given headers. The client passes all
+  /// headers explicitly, including `Authorization` and `Content-Type`.
+  fn post(&self, url: &str, headers: &[(String, String)], body: &[u8])
+      -> Result<TransportResponse, TransportError>;
+
+  /// GET `url`, with the given headers, e.g. to look up a token. Transports
+  /// that don't override this fail with an error.
+  fn get(&self, url: &str, _headers: &[(String, String)])
+      -> Result<TransportResponse, TransportError> {
+    Err(TransportError::new(format!("GET {} isn't supported by this transport", url)))
+  }
+}
+
+// Look up a header value by its case insensitive name.
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+  headers.iter()
+    .find(|&&(ref header, _)| header.eq_ignore_ascii_case(name))
+    .map(|&(_, ref value)| &value[..])
+}
+
+/// An HTTP response, as returned by a `Transport`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TransportResponse {
+  pub status: u16,
+  pub headers: Vec<(String, String)>,
+  pub body: String,
+  /// The bytes received, if they aren't valid UTF-8 or the body was cut
+  /// short. `body` then holds them decoded lossily.
+  pub raw_body: Option<Vec<u8>>,
+}
+
+impl TransportResponse {
+  /// Get a new `TransportResponse` with the given status code and body,
+  /// and no headers.
+  pub fn new(status: u16, body: &str) -> TransportResponse {
+    TransportResponse {
+      status: status,
+      headers: Vec::new(),
+      body: body.to_string(),
+      raw_body: None,
+    }
+  }
+
+  /// Get a new `TransportResponse` with the given status code and body
+  /// bytes, and no headers. Bytes that aren't valid UTF-8 are kept in
+  /// `raw_body`.
+  pub fn from_bytes(status: u16, body: Vec<u8>) -> TransportResponse {
+    match String::from_utf8(body) {
+      Ok(body) => TransportResponse::new(status, &body),
+      Err(error) => TransportResponse {
+        status: status,
+        headers: Vec::new(),
+        body: String::from_utf8_lossy(error.as_bytes()).into_owned(),
+        raw_body: Some(error.into_bytes()),
+      }
+    }
+  }
+
+  /// Add a header to the response.
+  pub fn header(mut self, name: &str, value: &str) -> TransportResponse {
+    self.headers.push((name.to_string(), value.to_string()));
+    self
+  }
+
+  /// Look up the value of a header. Header names are case insensitive.
+  pub fn get_header(&self, name: &str) -> Option<&str> {
+    find_header(&self.headers, name)
+  }
+}
+
+/// A failure to get any response from the server, such as a connection or
+/// TLS error. The underlying error is available through `source`.
+#[derive(Debug)]
+pub struct TransportError {
+  cause: Box<dyn error::Error + Send + Sync>,
+  tls: bool,
+}
+
+impl TransportError {
+  /// Wrap the underlying error. Plain strings work too.
+  pub fn new<E>(cause: E) -> TransportError
+      where E: Into<Box<dyn error::Error + Send + Sync>> {
+    TransportError {
+      cause: cause.into(),
+      tls: false,
+    }
+  }
+
+  /// Wrap an error from setting up TLS, before any request was made.
+  pub fn tls<E>(cause: E) -> TransportError
+      where E: Into<Box<dyn error::Error + Send + Sync>> {
+    TransportError {
+      cause: cause.into(),
+      tls: true,
+    }
+  }
+
+  /// Whether this is an error from setting up TLS.
+  pub fn is_tls(&self) -> bool {
+    self.tls
+  }
+}
+
+impl Display for TransportError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.cause)
+  }
+}
+
+impl error::Error for TransportError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    Some(&*self.cause)
+  }
+}
+
+// The underlying errors can't be compared, so compare their messages.
+impl PartialEq for TransportError {
+  fn eq(&self, other: &TransportError) -> bool {
+    self.tls == other.tls && self.to_string() == other.to_string()
+  }
+}
+
+/// The transport `GcmClient` uses unless given another: `HyperTransport`,
+/// or `ReqwestTransport` with the `reqwest` feature.
+#[cfg(not(feature = "reqwest"))]
+pub type DefaultTransport = HyperTransport;
+
+/// The transport `GcmClient` uses unless given another: `HyperTransport`,
+/// or `ReqwestTransport` with the `reqwest` feature.
+#[cfg(feature = "reqwest")]
+pub type DefaultTransport = ReqwestTransport;
+
+/// How many idle connections the default transport keeps open per host.
+pub const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = 5;
+
+/// How long the default transport keeps idle connections open.
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// The default `Transport`, which sends requests over HTTPS using hyper.
+/// The hyper client, and its pool of connections, is created on the first
+/// request and reused after that. Clones start with a pool of their own.
+pub struct HyperTransport {
+  accept_invalid_certs: bool,
+  root_certificates: Vec<RootCertificate>,
+  pool_max_idle_per_host: usize,
+  pool_idle_timeout: Option<Duration>,
+  timeout: Option<Duration>,
+  client: Mutex<Option<CachedClient>>,
+}
+
+struct CachedClient {
+  client: Arc<Client>,
+  last_used: Instant,
+}
+
+impl Clone for HyperTransport {
+  fn clone(&self) -> HyperTransport {
+    HyperTransport {
+      accept_invalid_certs: self.accept_invalid_certs,
+      root_certificates: self.root_certificates.clone(),
+      pool_max_idle_per_host: self.pool_max_idle_per_host,
+      pool_idle_timeout: self.pool_idle_timeout,
+      timeout: self.timeout,
+      client: Mutex::new(None),
+    }
+  }
+}
+
+// Certificates are kept as bytes, and parsed again for each connector.
+#[derive(Debug, Clone)]
+enum RootCertificate {
+  Der(Vec<u8>),
+  Pem(Vec<u8>),
+}
+
+impl RootCertificate {
+  fn parse(&self) -> Result<Certificate, TransportError> {
+    let certificate = match *self {
+      RootCertificate::Der(ref der) => Certificate::from_der(der),
+      RootCertificate::Pem(ref pem) => Certificate::from_pem(pem),
+    };
+
+    certificate.map_err(TransportError::tls)
+  }
+}
+
+impl HyperTransport {
+  pub fn new() -> HyperTransport {
+    HyperTransport {
+      accept_invalid_certs: false,
+      root_certificates: Vec::new(),
+      pool_max_idle_per_host: DEFAULT_POOL_MAX_IDLE_PER_HOST,
+      pool_idle_timeout: Some(DEFAULT_POOL_IDLE_TIMEOUT),
+      timeout: None,
+      client: Mutex::new(None),
+    }
+  }
+
+  /// How many idle connections to keep open per host, ready for the next
+  /// request. Defaults to `DEFAULT_POOL_MAX_IDLE_PER_HOST`.
+  pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> HyperTransport {
+    self.pool_max_idle_per_host = max_idle;
+    self
+  }
+
+  /// How long to keep idle connections open. After this long without a
+  /// request, the pool is dropped and new connections are made. `None`
+  /// keeps them as long as the server does. Defaults to
+  /// `DEFAULT_POOL_IDLE_TIMEOUT`.
+  pub fn pool_idle_timeout(mut self, idle_timeout: Option<Duration>) -> HyperTransport {
+    self.pool_idle_timeout = idle_timeout;
+    self
+  }
+
+  /// How long to wait for the server when writing a request or reading a
+  /// response, `None` to wait as long as it takes. Defaults to `None`.
+  pub fn timeout(mut self, timeout: Option<Duration>) -> HyperTransport {
+    self.timeout = timeout;
+    self
+  }
+
+  /// Trust an additional root certificate, in DER or PEM format, e.g. the
+  /// CA of a TLS-terminating proxy. Returns a TLS error if the
+  /// certificate can't be parsed.
+  pub fn add_root_certificate(mut self, der_or_pem: &[u8]) -> Result<HyperTransport, TransportError> {
+    let certificate = if is_pem(der_or_pem) {
+      RootCertificate::Pem(der_or_pem.to_vec())
+    } else {
+      RootCertificate::Der(der_or_pem.to_vec())
+    };
+
+    certificate.parse()?;
+    self.root_certificates.push(certificate);
+    Ok(self)
+  }
+
+  /// Accept any TLS certificate from the server, even an invalid one.
+  /// Dangerous; see `GcmClient::danger_accept_invalid_certs`.
+  #[cfg(any(debug_assertions, feature = "danger-insecure-tls"))]
+  pub fn danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> HyperTransport {
+    self.accept_invalid_certs = accept_invalid_certs;
+    self
+  }
+
+  /// Whether invalid TLS certificates are accepted.
+  pub fn accepts_invalid_certs(&self) -> bool {
+    self.accept_invalid_certs
+  }
+}
+
+// Read the body of a response. What was received of a body cut short is
+// kept, unless that's nothing.
+fn read_response<R: Read>(status: u16, headers: Vec<(String, String)>, reader: &mut R)
+    -> Result<TransportResponse, TransportError> {
+  let mut body = Vec::new();
+  let complete = match reader.read_to_end(&mut body) {
+    Ok(_) => true,
+    Err(_) if !body.is_empty() => false,
+    Err(error) => return Err(TransportError::new(error))
+  };
+
+  let mut response = TransportResponse::from_bytes(status, body);
+  if !complete && response.raw_body.is_none() {
+    response.raw_body = Some(response.body.clone().into_bytes());
+  }
+  response.headers = headers;
+
+  Ok(response)
+}
+
+fn is_pem(certificate: &[u8]) -> bool {
+  let start = certificate.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(0);
+  certificate[start..].starts_with(b"-----BEGIN")
+}
+
+impl Default for HyperTransport {
+  fn default() -> HyperTransport {
+    HyperTransport::new()
+  }
+}
+
+impl HyperTransport {
+  // The cached client, unless it has been idle for too long.
+  fn client(&self) -> Result<Arc<Client>, TransportError> {
+    let mut cached = self.client.lock().unwrap();
+    let now = Instant::now();
+
+    if let Some(ref mut current) = *cached {
+      let idle = now.duration_since(current.last_used);
+
+      if self.pool_idle_timeout.map_or(true, |idle_timeout| idle < idle_timeout) {
+        current.last_used = now;
+        return Ok(current.client.clone());
+      }
+    }
+
+    let client = Arc::new(self.build_client()?);
+    *cached = Some(CachedClient {
+      client: client.clone(),
+      last_used: now,
+    });
+    Ok(client)
+  }
+
+  fn build_client(&self) -> Result<Client, TransportError> {
+    let mut builder = TlsConnector::builder();
+    builder.danger_accept_invalid_certs(self.accept_invalid_certs);
+    for certificate in &self.root_certificates {
+      builder.add_root_certificate(certificate.parse()?);
+    }
+    let tls = builder.build().map_err(TransportError::tls)?;
+    let connector = HttpsConnector::new(NativeTlsClient::from(tls));
+    let pool = Pool::with_connector(Config { max_idle: self.pool_max_idle_per_host }, connector);
+
+    let mut client = Client::with_connector(pool);
+    client.set_read_timeout(self.timeout);
+    client.set_write_timeout(self.timeout);
+    Ok(client)
+  }
+}
+
+impl Transport for HyperTransport {
+  fn post(&self, url: &str, headers: &[(String, String)], body: &[u8])
+      -> Result<TransportResponse, TransportError> {
+    self.request(Method::Post, url, headers, Some(body))
+  }
+
+  fn get(&self, url: &str, headers: &[(String, String)])
+      -> Result<TransportResponse, TransportError> {
+    self.request(Method::Get, url, headers, None)
+  }
+}
+
+impl HyperTransport {
+  fn request(&self, method: Method, url: &str, headers: &[(String, String)], body: Option<&[u8]>)
+      -> Result<TransportResponse, TransportError> {
+    let client = self.client()?;
+
+    let mut request_headers = Headers::new();
+    for &(ref name, ref value) in headers {
+      request_headers.set_raw(name.clone(), vec![value.as_bytes().to_vec()]);
+    }
+
+    let mut request = client.request(method, url)
+            .headers(request_headers);
+    if let Some(body) = body {
+      request = request.body(body);
+    }
+    let mut res = request.send().map_err(TransportError::new)?;
+
+    let headers = res.headers.iter()
+      .map(|header| (header.name().to_string(), header.value_string()))
+      .collect();
+
+    read_response(res.status.to_u16(), headers, &mut res)
+  }
+}
+
+/// A request received by a `MockTransport`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct MockRequest {
+  pub method: String,
+  pub url: String,
+  pub headers: Vec<(String, String)>,
+  pub body: Vec<u8>,
+}
+
+impl MockRequest {
+  /// Look up the value of a header. Header names are case insensitive.
+  pub fn header(&self, name: &str) -> Option<&str> {
+    find_header(&self.headers, name)
+  }
+}
+
+/// A `Transport` that answers with canned responses and records the
+/// requests it receives. Clones share the same responses and requests, so
+/// keep a clone around to inspect it after handing it to a `GcmClient`.
+///
+/// # Examples
+///
+/// ```rust
+/// use gcm::{GcmClient, Message, MockTransport};
+///
+/// let transport = MockTransport::new()
+///     .respond(200, r#"{"message_id": "0:1"}"#);
+/// let client = GcmClient::new().transport(transport.clone());
+///
+/// let message = Message::new("<registration id>").content_available(true);
+/// let response = client.send(&message, "<API key>").unwrap();
+///
+/// assert_eq!(response.message_id, Some("0:1".to_string()));
+/// assert_eq!(transport.requests().len(), 1);
+/// ```
+#[derive(Clone)]
+pub struct MockTransport {
+  responses: Arc<Mutex<Vec<Result<TransportResponse, String>>>>,
+  requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockTransport {
+  /// Get a new `MockTransport` with no responses queued.
+  pub fn new() -> MockTransport {
+    MockTransport {
+      responses: Arc::new(Mutex::new(Vec::new())),
+      requests: Arc::new(Mutex::new(Vec::new())),
+    }
+  }
+
+  /// Queue a response with the given status code and body.
+  pub fn respond(self, status: u16, body: &str) -> MockTransport {
+    self.respond_with(TransportResponse::new(status, body))
+  }
+
+  /// Queue a response with the given status code and raw body, which
+  /// needn't be valid UTF-8.
+  pub fn respond_bytes(self, status: u16, body: &[u8]) -> MockTransport {
+    self.respond_with(TransportResponse::from_bytes(status, body.to_vec()))
+  }
+
+  /// Queue a response.
+  pub fn respond_with(self, response: TransportResponse) -> MockTransport {
+    self.responses.lock().unwrap().push(Ok(response));
+    self
+  }
+
+  /// Queue a transport failure with the given message.
+  pub fn fail(self, message: &str) -> MockTransport {
+    self.responses.lock().unwrap().push(Err(message.to_string()));
+    self
+  }
+
+  /// The requests received so far, oldest first.
+  pub fn requests(&self) -> Vec<MockRequest> {
+    self.requests.lock().unwrap().clone()
+  }
+}
+
+impl Default for MockTransport {
+  fn default() -> MockTransport {
+    MockTransport::new()
+  }
+}
+
+impl Transport for MockTransport {
+  fn post(&self, url: &str, headers: &[(String, String)], body: &[u8])
+      -> Result<TransportResponse, TransportError> {
+    self.respond_to("POST", url, headers, body)
+  }
+
+  fn get(&self, url: &str, headers: &[(String, String)])
+      -> Result<TransportResponse, TransportError> {
+    self.respond_to("GET", url, headers, b"")
+  }
+}
+
+impl MockTransport {
+  // Queued responses are returned in order; the last one is repeated
+  // for any further requests.
+  fn respond_to(&self, method: &str, url: &str, headers: &[(String, String)], body: &[u8])
+      -> Result<TransportResponse, TransportError> {
+    self.requests.lock().unwrap().push(MockRequest {
+      method: method.to_string(),
+      url: url.to_string(),
+      headers: headers.to_vec(),
+      body: body.to_vec(),
+    });
+
+    let mut responses = self.responses.lock().unwrap();
+    let response = match responses.len() {
+      0 => return Err(TransportError::new("no response queued")),
+      1 => responses[0].clone(),
+      _ => responses.remove(0)
+    };
+
+    response.map_err(TransportError::new)
+  }
+}
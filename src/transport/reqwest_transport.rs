@@ -0,0 +1,170 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use reqwest::{Certificate, Method};
+use reqwest::blocking::Client;
+
+use super::{is_pem, read_response, Transport, TransportError, TransportResponse};
+use super::{DEFAULT_POOL_MAX_IDLE_PER_HOST, DEFAULT_POOL_IDLE_TIMEOUT};
+
+/// A `Transport` that sends requests over HTTPS using reqwest, in place of
+/// hyper 0.10 and its TLS stack. Enabled with the `reqwest` feature, which
+/// also makes it the default transport of `GcmClient`. The reqwest client
+/// is created on the first request and reused after that; it keeps idle
+/// connections by itself. Clones start with a client of their own.
+pub struct ReqwestTransport {
+  // Visible to the transport tests, like those of `HyperTransport`.
+  pub(super) accept_invalid_certs: bool,
+  pub(super) root_certificates: Vec<Vec<u8>>,
+  pub(super) pool_max_idle_per_host: usize,
+  pub(super) pool_idle_timeout: Option<Duration>,
+  pub(super) timeout: Option<Duration>,
+  client: Mutex<Option<Client>>,
+}
+
+impl Clone for ReqwestTransport {
+  fn clone(&self) -> ReqwestTransport {
+    ReqwestTransport {
+      accept_invalid_certs: self.accept_invalid_certs,
+      root_certificates: self.root_certificates.clone(),
+      pool_max_idle_per_host: self.pool_max_idle_per_host,
+      pool_idle_timeout: self.pool_idle_timeout,
+      timeout: self.timeout,
+      client: Mutex::new(None),
+    }
+  }
+}
+
+impl ReqwestTransport {
+  pub fn new() -> ReqwestTransport {
+    ReqwestTransport {
+      accept_invalid_certs: false,
+      root_certificates: Vec::new(),
+      pool_max_idle_per_host: DEFAULT_POOL_MAX_IDLE_PER_HOST,
+      pool_idle_timeout: Some(DEFAULT_POOL_IDLE_TIMEOUT),
+      timeout: None,
+      client: Mutex::new(None),
+    }
+  }
+
+  /// How many idle connections to keep open per host, ready for the next
+  /// request. Defaults to `DEFAULT_POOL_MAX_IDLE_PER_HOST`.
+  pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> ReqwestTransport {
+    self.pool_max_idle_per_host = max_idle;
+    self
+  }
+
+  /// How long to keep idle connections open. `None` keeps them as long as
+  /// the server does. Defaults to `DEFAULT_POOL_IDLE_TIMEOUT`.
+  pub fn pool_idle_timeout(mut self, idle_timeout: Option<Duration>) -> ReqwestTransport {
+    self.pool_idle_timeout = idle_timeout;
+    self
+  }
+
+  /// How long to wait for a whole request, from connecting to reading the
+  /// response, `None` to wait as long as it takes. Defaults to `None`.
+  pub fn timeout(mut self, timeout: Option<Duration>) -> ReqwestTransport {
+    self.timeout = timeout;
+    self
+  }
+
+  /// Trust an additional root certificate, in DER or PEM format, e.g. the
+  /// CA of a TLS-terminating proxy. Returns a TLS error if the
+  /// certificate can't be parsed.
+  pub fn add_root_certificate(mut self, der_or_pem: &[u8]) -> Result<ReqwestTransport, TransportError> {
+    parse_certificate(der_or_pem)?;
+    self.root_certificates.push(der_or_pem.to_vec());
+    Ok(self)
+  }
+
+  /// Accept any TLS certificate from the server, even an invalid one.
+  /// Dangerous; see `GcmClient::danger_accept_invalid_certs`.
+  #[cfg(any(debug_assertions, feature = "danger-insecure-tls"))]
+  pub fn danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> ReqwestTransport {
+    self.accept_invalid_certs = accept_invalid_certs;
+    self
+  }
+
+  /// Whether invalid TLS certificates are accepted.
+  pub fn accepts_invalid_certs(&self) -> bool {
+    self.accept_invalid_certs
+  }
+
+  // The cached client. reqwest clients are handles to a shared pool, so
+  // cloning one is cheap.
+  pub(super) fn client(&self) -> Result<Client, TransportError> {
+    let mut cached = self.client.lock().unwrap();
+
+    if let Some(ref client) = *cached {
+      return Ok(client.clone());
+    }
+
+    let client = self.build_client()?;
+    *cached = Some(client.clone());
+    Ok(client)
+  }
+
+  fn build_client(&self) -> Result<Client, TransportError> {
+    let mut builder = Client::builder()
+      .danger_accept_invalid_certs(self.accept_invalid_certs)
+      .pool_max_idle_per_host(self.pool_max_idle_per_host)
+      .pool_idle_timeout(self.pool_idle_timeout)
+      .timeout(self.timeout);
+    for certificate in &self.root_certificates {
+      builder = builder.add_root_certificate(parse_certificate(certificate)?);
+    }
+
+    builder.build().map_err(TransportError::tls)
+  }
+}
+
+fn parse_certificate(der_or_pem: &[u8]) -> Result<Certificate, TransportError> {
+  let certificate = if is_pem(der_or_pem) {
+    Certificate::from_pem(der_or_pem)
+  } else {
+    Certificate::from_der(der_or_pem)
+  };
+
+  certificate.map_err(TransportError::tls)
+}
+
+impl Default for ReqwestTransport {
+  fn default() -> ReqwestTransport {
+    ReqwestTransport::new()
+  }
+}
+
+impl Transport for ReqwestTransport {
+  fn post(&self, url: &str, headers: &[(String, String)], body: &[u8])
+      -> Result<TransportResponse, TransportError> {
+    self.request(Method::POST, url, headers, Some(body))
+  }
+
+  fn get(&self, url: &str, headers: &[(String, String)])
+      -> Result<TransportResponse, TransportError> {
+    self.request(Method::GET, url, headers, None)
+  }
+}
+
+impl ReqwestTransport {
+  fn request(&self, method: Method, url: &str, headers: &[(String, String)], body: Option<&[u8]>)
+      -> Result<TransportResponse, TransportError> {
+    let client = self.client()?;
+
+    let mut request = client.request(method, url);
+    if let Some(body) = body {
+      request = request.body(body.to_vec());
+    }
+    for &(ref name, ref value) in headers {
+      request = request.header(&name[..], &value[..]);
+    }
+
+    let mut res = request.send().map_err(TransportError::new)?;
+
+    let headers = res.headers().iter()
+      .map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+      .collect();
+
+    read_response(res.status().as_u16(), headers, &mut res)
+  }
+}
@@ -0,0 +1,232 @@
+use {Transport, TransportError, TransportResponse, HyperTransport, MockTransport, MockRequest};
+use {DefaultTransport, DEFAULT_POOL_MAX_IDLE_PER_HOST, DEFAULT_POOL_IDLE_TIMEOUT};
+#[cfg(feature = "reqwest")]
+use ReqwestTransport;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn should_find_headers_case_insensitively() {
+  let response = TransportResponse::new(503, "")
+      .header("Retry-After", "120");
+
+  assert_eq!(response.get_header("retry-after"), Some("120"));
+  assert_eq!(response.get_header("Content-Type"), None);
+}
+
+#[test]
+fn should_keep_bytes_that_are_not_utf8() {
+  let response = TransportResponse::from_bytes(200, b"ok".to_vec());
+
+  assert_eq!(response.body, "ok");
+  assert_eq!(response.raw_body, None);
+
+  let response = TransportResponse::from_bytes(200, b"o\xffk".to_vec());
+
+  assert_eq!(response.body, "o\u{fffd}k");
+  assert_eq!(response.raw_body, Some(b"o\xffk".to_vec()));
+}
+
+#[test]
+fn should_record_mock_requests() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+
+  let headers = vec![("Authorization".to_string(), "key=key".to_string())];
+  let response = transport.post("https://example.com", &headers, b"{}");
+
+  assert_eq!(response.unwrap(), TransportResponse::new(200, "{}"));
+  assert_eq!(transport.requests(), vec![MockRequest {
+    method: "POST".to_string(),
+    url: "https://example.com".to_string(),
+    headers: headers.clone(),
+    body: b"{}".to_vec(),
+  }]);
+  assert_eq!(transport.requests()[0].header("authorization"), Some("key=key"));
+}
+
+#[test]
+fn should_return_mock_responses_in_order_and_repeat_the_last() {
+  let transport = MockTransport::new()
+      .fail("connection refused")
+      .respond(503, "")
+      .respond(200, "{}");
+
+  let first = transport.post("url", &[], b"");
+  assert_eq!(first.err().unwrap().to_string(), "connection refused");
+  assert_eq!(transport.post("url", &[], b"").unwrap().status, 503);
+  assert_eq!(transport.post("url", &[], b"").unwrap().status, 200);
+  assert_eq!(transport.post("url", &[], b"").unwrap().status, 200);
+  assert_eq!(transport.requests().len(), 4);
+}
+
+#[test]
+fn should_share_state_between_mock_clones() {
+  let transport = MockTransport::new()
+      .respond(200, "{}");
+  let clone = transport.clone();
+
+  clone.post("url", &[], b"").unwrap();
+
+  assert_eq!(transport.requests().len(), 1);
+}
+
+#[test]
+fn should_fail_without_queued_responses() {
+  let transport = MockTransport::new();
+
+  assert!(transport.post("url", &[], b"").is_err());
+}
+
+
+#[test]
+fn should_expose_the_cause_of_transport_errors() {
+  let error = TransportError::new("connection refused");
+
+  assert!(!error.is_tls());
+  assert_eq!(error.source().unwrap().to_string(), "connection refused");
+  assert!(TransportError::tls("bad certificate").is_tls());
+  assert!(TransportError::tls("bad certificate") != TransportError::new("bad certificate"));
+}
+#[cfg(any(debug_assertions, feature = "danger-insecure-tls"))]
+#[test]
+fn should_verify_certificates_by_default() {
+  assert!(!HyperTransport::new().accepts_invalid_certs());
+  assert!(HyperTransport::new().danger_accept_invalid_certs(true).accepts_invalid_certs());
+}
+
+#[test]
+fn should_add_root_certificates() {
+  let transport = HyperTransport::new()
+      .add_root_certificate(include_bytes!("test_ca.pem")).unwrap()
+      .add_root_certificate(include_bytes!("test_ca.der")).unwrap();
+
+  assert_eq!(transport.root_certificates.len(), 2);
+}
+
+#[test]
+fn should_reject_invalid_root_certificates() {
+  let error = HyperTransport::new().add_root_certificate(b"not a certificate").err().unwrap();
+
+  assert!(error.is_tls());
+  assert!(HyperTransport::new().add_root_certificate(b"-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n").is_err());
+}
+
+#[test]
+fn should_default_pool_config() {
+  let transport = HyperTransport::new();
+
+  assert_eq!(transport.pool_max_idle_per_host, DEFAULT_POOL_MAX_IDLE_PER_HOST);
+  assert_eq!(transport.pool_idle_timeout, Some(DEFAULT_POOL_IDLE_TIMEOUT));
+
+  let transport = transport
+      .pool_max_idle_per_host(32)
+      .pool_idle_timeout(None);
+
+  assert_eq!(transport.pool_max_idle_per_host, 32);
+  assert_eq!(transport.pool_idle_timeout, None);
+}
+
+#[test]
+fn should_set_timeout() {
+  let transport = HyperTransport::new();
+
+  assert_eq!(transport.timeout, None);
+
+  let transport = transport.timeout(Some(Duration::from_secs(5)));
+
+  assert_eq!(transport.timeout, Some(Duration::from_secs(5)));
+  assert!(transport.client().is_ok());
+}
+
+#[test]
+fn should_reuse_hyper_client() {
+  let transport = HyperTransport::new();
+
+  let first = transport.client().unwrap();
+  let second = transport.client().unwrap();
+
+  assert!(Arc::ptr_eq(&first, &second));
+  assert!(!Arc::ptr_eq(&first, &transport.clone().client().unwrap()));
+}
+
+#[test]
+fn should_replace_hyper_client_after_idle_timeout() {
+  let transport = HyperTransport::new().pool_idle_timeout(Some(Duration::from_secs(0)));
+
+  let first = transport.client().unwrap();
+  let second = transport.client().unwrap();
+
+  assert!(!Arc::ptr_eq(&first, &second));
+}
+
+#[test]
+fn should_keep_the_part_of_a_body_cut_short() {
+  struct CutShort(bool);
+
+  impl ::std::io::Read for CutShort {
+    fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+      if self.0 {
+        return Err(::std::io::Error::new(::std::io::ErrorKind::UnexpectedEof, "connection reset"));
+      }
+      self.0 = true;
+      buf[..4].copy_from_slice(b"{\"a\"");
+      Ok(4)
+    }
+  }
+
+  let response = super::read_response(200, Vec::new(), &mut CutShort(false)).unwrap();
+
+  assert_eq!(response.body, "{\"a\"");
+  assert_eq!(response.raw_body, Some(b"{\"a\"".to_vec()));
+  assert!(super::read_response(200, Vec::new(), &mut CutShort(true)).is_err());
+}
+
+#[cfg(not(feature = "reqwest"))]
+#[test]
+fn should_default_to_hyper() {
+  let transport: HyperTransport = DefaultTransport::new();
+
+  assert_eq!(transport.pool_max_idle_per_host, DEFAULT_POOL_MAX_IDLE_PER_HOST);
+}
+
+#[cfg(feature = "reqwest")]
+#[test]
+fn should_default_to_reqwest() {
+  let transport: ReqwestTransport = DefaultTransport::new();
+
+  assert_eq!(transport.pool_max_idle_per_host, DEFAULT_POOL_MAX_IDLE_PER_HOST);
+}
+
+#[cfg(feature = "reqwest")]
+#[test]
+fn should_add_root_certificates_to_reqwest() {
+  let transport = ReqwestTransport::new()
+      .add_root_certificate(include_bytes!("test_ca.pem")).unwrap()
+      .add_root_certificate(include_bytes!("test_ca.der")).unwrap();
+
+  assert_eq!(transport.root_certificates.len(), 2);
+  assert!(transport.client().is_ok());
+  assert!(ReqwestTransport::new().add_root_certificate(b"not a certificate").err().unwrap().is_tls());
+}
+
+#[cfg(feature = "reqwest")]
+#[test]
+fn should_configure_reqwest_pool() {
+  let transport = ReqwestTransport::new()
+      .pool_max_idle_per_host(32)
+      .pool_idle_timeout(None);
+
+  assert_eq!(transport.pool_max_idle_per_host, 32);
+  assert_eq!(transport.pool_idle_timeout, None);
+  assert!(!transport.accepts_invalid_certs());
+}
+
+#[cfg(feature = "reqwest")]
+#[test]
+fn should_fail_reqwest_requests_without_a_server() {
+  let error = ReqwestTransport::new().post("http://127.0.0.1:1/", &[], b"{}").err().unwrap();
+
+  assert!(!error.is_tls());
+}
@@ -0,0 +1,76 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+/// Options for messages delivered to browsers with the Web Push protocol,
+/// sent under `webpush`. Only FCM understands it; GCM ignores it.
+/// # Examples:
+/// ```rust
+/// use gcm::{Message, WebpushConfig};
+///
+/// let webpush = WebpushConfig::new()
+///     .ttl(3600)
+///     .link("https://example.com/scores");
+///
+/// let message = Message::new("<registration id>").webpush(webpush);
+/// ```
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebpushConfig {
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  headers: HashMap<String, String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  notification: Option<Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  fcm_options: Option<WebpushFcmOptions>,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+struct WebpushFcmOptions {
+  link: String,
+}
+
+impl WebpushConfig {
+  /// Get a new, empty `WebpushConfig`.
+  pub fn new() -> WebpushConfig {
+    WebpushConfig::default()
+  }
+
+  /// Add a Web Push header, such as `Urgency`.
+  pub fn header(mut self, name: &str, value: &str) -> WebpushConfig {
+    self.headers.insert(name.to_string(), value.to_string());
+    self
+  }
+
+  /// How long (in seconds) the push service should keep the message if the
+  /// browser is offline. Sets the `TTL` header.
+  pub fn ttl(self, seconds: u32) -> WebpushConfig {
+    self.header("TTL", &seconds.to_string())
+  }
+
+  /// Set the Web Notification to show, as JSON with fields like `title`,
+  /// `body` and `icon`.
+  pub fn notification(mut self, notification: Value) -> WebpushConfig {
+    self.notification = Some(notification);
+    self
+  }
+
+  /// The page to open when the user clicks on the notification. Must be
+  /// an HTTPS URL.
+  pub fn link(mut self, link: &str) -> WebpushConfig {
+    self.fcm_options = Some(WebpushFcmOptions { link: link.to_string() });
+    self
+  }
+
+  /// The Web Push headers.
+  pub fn headers(&self) -> &HashMap<String, String> {
+    &self.headers
+  }
+
+  /// The link opened on click, if set.
+  pub fn get_link(&self) -> Option<&str> {
+    self.fcm_options.as_ref().map(|options| &options.link[..])
+  }
+}
@@ -0,0 +1,34 @@
+use serde_json;
+use WebpushConfig;
+
+#[test]
+fn should_serialize_empty_webpush_config() {
+  let j = serde_json::to_value(&WebpushConfig::new()).unwrap();
+
+  assert_eq!(j, json!({}));
+}
+
+#[test]
+fn should_serialize_webpush_config() {
+  let webpush = WebpushConfig::new()
+    .header("Urgency", "high")
+    .ttl(3600)
+    .notification(json!({ "title": "India vs. Australia", "icon": "/icon.png" }))
+    .link("https://example.com/scores");
+
+  let j = serde_json::to_value(&webpush).unwrap();
+
+  assert_eq!(j, json!({
+    "headers": { "Urgency": "high", "TTL": "3600" },
+    "notification": { "title": "India vs. Australia", "icon": "/icon.png" },
+    "fcm_options": { "link": "https://example.com/scores" }
+  }));
+}
+
+#[test]
+fn should_read_back_webpush_config() {
+  let webpush = WebpushConfig::new().ttl(60).link("https://example.com");
+
+  assert_eq!(webpush.headers().get("TTL").map(|ttl| &ttl[..]), Some("60"));
+  assert_eq!(webpush.get_link(), Some("https://example.com"));
+}